@@ -0,0 +1,281 @@
+//! fresh-miner: off-chain nonce solver for MineWithProof.
+//!
+//!   fresh-miner solve --state <dump> --miner <pubkey> --token-account <pubkey> --program-id <pubkey>
+//!                     [--config <dump>] [--now <unix time>] [--threads <n>]
+//!   fresh-miner bench [--difficulty <n>] [--seconds <n>] [--threads <n>]
+//!
+//! Dumps are raw account data, e.g. from `solana account <address> --output-file <dump>`.
+//! Every mine rotates the challenge in the state, so dump it right before solving.
+
+use std::collections::HashMap;
+use std::process::exit;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use borsh::BorshSerialize;
+use mr_fresh::*;
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    sysvar::clock::ID as CLOCK_ID,
+};
+
+// Hashes per thread between checks of the shared stop flag
+const BATCH_SIZE: u64 = 4096;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = parse_options(args.get(1..).unwrap_or_default());
+    let threads = options
+        .get("threads")
+        .map(|n| parse(n, "threads"))
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get() as u64))
+        .max(1);
+
+    match args.first().map(String::as_str) {
+        Some("solve") => solve(&options, threads),
+        Some("bench") => bench(&options, threads),
+        _ => fail("usage: fresh-miner <solve|bench> [options], see the source header"),
+    }
+}
+
+fn solve(options: &HashMap<String, String>, threads: u64) {
+    let state_data = read(required(options, "state"));
+    let state = unpack_account::<MrFreshState>(&state_data).unwrap_or_else(|_| fail("state dump is not a MrFreshState account"));
+    if state.version != STATE_VERSION {
+        fail(&format!("state is at version {}, expected {}; run Migrate", state.version, STATE_VERSION));
+    }
+    if state.paused {
+        eprintln!("warning: mining is paused, Mine will fail until Unpause");
+    }
+    let config = match options.get("config") {
+        Some(path) => unpack_account::<FreshConfig>(&read(path)).unwrap_or_else(|_| fail("config dump is not a FreshConfig account")),
+        None => FreshConfig::default(),
+    };
+    let miner = pubkey(required(options, "miner"));
+    let token_account = pubkey(required(options, "token-account"));
+    let program_id = pubkey(required(options, "program-id"));
+    let now = options.get("now").map(|t| parse(t, "now") as i64).unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+    });
+
+    let view = bytemuck::try_from_bytes::<MrFreshStateView>(&state_data)
+        .unwrap_or_else(|_| fail("state dump has the wrong size for this version"));
+    match expected_mining_reward(view, &config, now) {
+        // Counts on the energy burst being ready, which the program checks
+        // against the slot it lands in
+        Ok(reward) => println!(
+            "expected reward: {} (RevealMine rolls for poop and bonuses on a base of {})",
+            single_transaction_reward(reward, &config, state.poop_chance, true),
+            reward
+        ),
+        Err(_) => eprintln!("warning: emission has ended, Mine will fail"),
+    }
+    let emission = EmissionSchedule::new(state.emission_mode, state.initialization_timestamp, state.total_supply, &config)
+        .emission_at(now, state.mining_difficulty);
+    println!(
+        "halving epoch: {} (next halving at {}), remaining supply: {}",
+        emission.epoch, emission.next_halving_at, emission.remaining_supply
+    );
+    if !state.proof_required {
+        println!("proofs are optional here, plain Mine pays the same");
+    }
+    if state.poop_chance > 0 {
+        println!(
+            "poop chance: 1 in {}, so MineWithProof pays {}/{} of the reward and a poop on a reveal pays nothing{}",
+            state.poop_chance,
+            state.poop_chance - 1,
+            state.poop_chance,
+            if state.poop_consolation { " for half the nap" } else { "" }
+        );
+    }
+
+    println!("searching difficulty {} on {} threads", state.mining_difficulty, threads);
+    let started = Instant::now();
+    let (nonce, hashes) = search(&state.challenge, &miner, state.mining_difficulty, threads);
+    let elapsed = started.elapsed();
+    println!("nonce: {}", nonce);
+    println!("hashes: {} in {:.2?} ({:.0} H/s)", hashes, elapsed, hashes as f64 / elapsed.as_secs_f64());
+
+    let instruction = mine_with_proof_instruction(&program_id, &state, &miner, &token_account, nonce);
+    println!("program: {}", instruction.program_id);
+    for account in &instruction.accounts {
+        println!(
+            "account: {} {} {}",
+            account.pubkey,
+            if account.is_signer { "signer" } else { "-" },
+            if account.is_writable { "writable" } else { "readonly" },
+        );
+    }
+    println!("data: {}", hex(&instruction.data));
+}
+
+fn mine_with_proof_instruction(program_id: &Pubkey, state: &MrFreshState, miner: &Pubkey, token_account: &Pubkey, nonce: u64) -> Instruction {
+    let mut data = Vec::new();
+    MrFreshInstruction::MineWithProof { nonce }.serialize(&mut data).unwrap();
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(find_state_address(program_id).0, false),
+            AccountMeta::new(*miner, true),
+            AccountMeta::new_readonly(CLOCK_ID, false),
+            AccountMeta::new(state.mint, false),
+            AccountMeta::new(*token_account, false),
+            AccountMeta::new_readonly(find_mint_authority_address(program_id).0, false),
+            AccountMeta::new_readonly(state.token_program, false),
+            AccountMeta::new(find_miner_address(program_id, miner).0, false),
+            AccountMeta::new_readonly(solana_system_interface::program::ID, false),
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        ],
+        data,
+    }
+}
+
+fn bench(options: &HashMap<String, String>, threads: u64) {
+    let difficulty = options.get("difficulty").map_or(u64::MAX, |d| parse(d, "difficulty"));
+    let seconds = options.get("seconds").map_or(10, |s| parse(s, "seconds"));
+    let challenge = [7u8; 32];
+    let miner = Pubkey::new_unique();
+
+    println!("benchmarking {} threads for {} seconds", threads, seconds);
+    let stop = AtomicBool::new(false);
+    let hashes = AtomicU64::new(0);
+    let started = Instant::now();
+    std::thread::scope(|scope| {
+        for thread in 0..threads {
+            let (stop, hashes) = (&stop, &hashes);
+            scope.spawn(move || {
+                let mut nonce = thread * BATCH_SIZE;
+                while !stop.load(Ordering::Relaxed) {
+                    for _ in 0..BATCH_SIZE {
+                        std::hint::black_box(meets_difficulty(&proof_hash(&challenge, &miner, nonce), difficulty));
+                        nonce += 1;
+                    }
+                    nonce += (threads - 1) * BATCH_SIZE;
+                    hashes.fetch_add(BATCH_SIZE, Ordering::Relaxed);
+                }
+            });
+        }
+        std::thread::sleep(Duration::from_secs(seconds));
+        stop.store(true, Ordering::Relaxed);
+    });
+    let total = hashes.load(Ordering::Relaxed);
+    let rate = total as f64 / started.elapsed().as_secs_f64();
+    println!("hashes: {}", total);
+    println!("rate: {:.0} H/s ({:.0} H/s per thread)", rate, rate / threads as f64);
+}
+
+/// Splits the nonce space into interleaved batches, one stream per thread,
+/// and returns the first valid nonce found with the number of hashes tried.
+fn search(challenge: &[u8; 32], miner: &Pubkey, difficulty: u64, threads: u64) -> (u64, u64) {
+    let found = AtomicBool::new(false);
+    let winner = AtomicU64::new(0);
+    let hashes = AtomicU64::new(0);
+    std::thread::scope(|scope| {
+        for thread in 0..threads {
+            let (found, winner, hashes) = (&found, &winner, &hashes);
+            scope.spawn(move || {
+                let mut batch = thread;
+                while !found.load(Ordering::Relaxed) {
+                    let start = batch * BATCH_SIZE;
+                    for nonce in start..start + BATCH_SIZE {
+                        if meets_difficulty(&proof_hash(challenge, miner, nonce), difficulty) {
+                            if !found.swap(true, Ordering::Relaxed) {
+                                winner.store(nonce, Ordering::Relaxed);
+                            }
+                            hashes.fetch_add(nonce - start + 1, Ordering::Relaxed);
+                            return;
+                        }
+                    }
+                    hashes.fetch_add(BATCH_SIZE, Ordering::Relaxed);
+                    batch += threads;
+                }
+            });
+        }
+    });
+    (winner.load(Ordering::Relaxed), hashes.load(Ordering::Relaxed))
+}
+
+fn parse_options(args: &[String]) -> HashMap<String, String> {
+    let mut options = HashMap::new();
+    for pair in args.chunks(2) {
+        match pair {
+            [key, value] if key.starts_with("--") => {
+                options.insert(key.trim_start_matches("--").to_string(), value.clone());
+            }
+            _ => fail(&format!("unexpected argument: {}", pair[0])),
+        }
+    }
+    options
+}
+
+fn required<'a>(options: &'a HashMap<String, String>, name: &str) -> &'a str {
+    options.get(name).unwrap_or_else(|| fail(&format!("missing --{}", name)))
+}
+
+fn parse(value: &str, name: &str) -> u64 {
+    value.parse().unwrap_or_else(|_| fail(&format!("--{} must be a number", name)))
+}
+
+fn pubkey(value: &str) -> Pubkey {
+    Pubkey::from_str(value).unwrap_or_else(|_| fail(&format!("invalid public key: {}", value)))
+}
+
+fn read(path: &str) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| fail(&format!("cannot read {}: {}", path, e)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("fresh-miner: {}", message);
+    exit(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshDeserialize;
+
+    #[test]
+    fn test_search_finds_valid_nonce() {
+        let challenge = [9u8; 32];
+        let miner = Pubkey::new_unique();
+        let difficulty = 5000;
+
+        // Threads race on interleaved batches, so whichever wins must still be valid
+        for threads in [1, 4] {
+            let (nonce, hashes) = search(&challenge, &miner, difficulty, threads);
+            assert!(meets_difficulty(&proof_hash(&challenge, &miner, nonce), difficulty), "nonce {} on {} threads", nonce, threads);
+            assert!(hashes > 0);
+        }
+    }
+
+    #[test]
+    fn test_solve_instruction_decodes_as_mine_with_proof() {
+        let program_id = Pubkey::new_unique();
+        let miner = Pubkey::new_unique();
+        let token_account = Pubkey::new_unique();
+        let mut data = MrFreshState::DISCRIMINATOR.to_vec();
+        data.resize(MrFreshState::LEN, 0);
+        let view = bytemuck::from_bytes_mut::<MrFreshStateView>(&mut data);
+        view.version = STATE_VERSION;
+        view.mint = Pubkey::new_unique();
+        view.token_program = Pubkey::new_unique();
+        let state = unpack_account::<MrFreshState>(&data).unwrap();
+
+        let instruction = mine_with_proof_instruction(&program_id, &state, &miner, &token_account, 0xfeed_beef);
+        match MrFreshInstruction::try_from_slice(&instruction.data).unwrap() {
+            MrFreshInstruction::MineWithProof { nonce } => assert_eq!(nonce, 0xfeed_beef),
+            other => panic!("expected MineWithProof, got {:?}", other),
+        }
+        assert_eq!(instruction.program_id, program_id);
+        assert_eq!(instruction.accounts[1], AccountMeta::new(miner, true));
+        assert_eq!(instruction.accounts[3].pubkey, state.mint);
+        assert_eq!(instruction.accounts[4].pubkey, token_account);
+        assert_eq!(instruction.accounts[7].pubkey, find_miner_address(&program_id, &miner).0);
+    }
+}
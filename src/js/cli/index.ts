@@ -1,6 +1,6 @@
 import { program } from 'commander';
 import { Connection, Keypair, PublicKey } from '@solana/web3.js';
-import { MrFreshSDK } from '../sdk';
+import { MrFreshSDK, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID } from '../sdk';
 import fs from 'fs';
 
 program
@@ -10,9 +10,14 @@ program
 program
   .command('init')
   .description('Initialize Mr. Fresh state')
-  .requiredOption('-k, --keypair <path>', 'Keypair file path')
+  .requiredOption('-k, --keypair <path>', 'Upgrade authority keypair file path')
+  .requiredOption('-m, --mint <pubkey>', 'FRESH mint public key')
+  .option('--token-2022', 'Mint is owned by the Token-2022 program')
   .option('-d, --difficulty <number>', 'Initial mining difficulty', '1000')
   .option('-b, --burst-duration <number>', 'Energy burst duration', '100')
+  .option('--allow-plain-mine', 'Also accept Mine without a proof of work')
+  .option('--poop-chance <number>', '1 in N mines finds poop, 0 disables', '0')
+  .option('--poop-consolation', 'Halve the nap after a poop')
   .action(async (options) => {
     const connection = new Connection('http://localhost:8899', 'confirmed');
     const payerKeypair = Keypair.fromSecretKey(
@@ -20,18 +25,22 @@ program
     );
     
     const sdk = new MrFreshSDK(connection, process.env.PROGRAM_ID!);
-    const stateAccount = new Keypair();
     
     try {
       const tx = await sdk.initialize(
         payerKeypair,
-        stateAccount,
+        new PublicKey(options.mint),
+        options.token2022 ? TOKEN_2022_PROGRAM_ID : TOKEN_PROGRAM_ID,
         parseInt(options.difficulty),
-        parseInt(options.burstDuration)
+        parseInt(options.burstDuration),
+        undefined,
+        !options.allowPlainMine,
+        parseInt(options.poopChance),
+        !!options.poopConsolation
       );
       console.log('Initialization successful!');
       console.log('Transaction:', tx);
-      console.log('State Account:', stateAccount.publicKey.toBase58());
+      console.log('State Account:', sdk.findStateAddress().toBase58());
     } catch (error) {
       console.error('Error:', error);
     }
@@ -40,22 +49,23 @@ program
 program
   .command('mine')
   .description('Mine FRESH tokens')
-  .requiredOption('-k, --keypair <path>', 'Keypair file path')
-  .requiredOption('-s, --state <pubkey>', 'State account public key')
+  .requiredOption('-k, --keypair <path>', 'Miner keypair file path')
+  .requiredOption('-t, --token-account <pubkey>', 'Miner FRESH token account')
+  .option('-n, --nonce <number>', 'Proof-of-work nonce from fresh-miner solve')
   .action(async (options) => {
     const connection = new Connection('http://localhost:8899', 'confirmed');
-    const payerKeypair = Keypair.fromSecretKey(
+    const minerKeypair = Keypair.fromSecretKey(
       new Uint8Array(JSON.parse(fs.readFileSync(options.keypair, 'utf-8')))
     );
     
     const sdk = new MrFreshSDK(connection, process.env.PROGRAM_ID!);
-    const minerKeypair = new Keypair();
     
     try {
       const tx = await sdk.mine(
-        payerKeypair,
-        new PublicKey(options.state),
-        minerKeypair
+        minerKeypair,
+        minerKeypair,
+        new PublicKey(options.tokenAccount),
+        options.nonce === undefined ? undefined : BigInt(options.nonce)
       );
       console.log('Mining successful!');
       console.log('Transaction:', tx);
@@ -67,13 +77,15 @@ program
 program
   .command('state')
   .description('Get current state')
-  .requiredOption('-s, --state <pubkey>', 'State account public key')
+  .option('-s, --state <pubkey>', 'State account public key (defaults to the state PDA)')
   .action(async (options) => {
     const connection = new Connection('http://localhost:8899', 'confirmed');
     const sdk = new MrFreshSDK(connection, process.env.PROGRAM_ID!);
     
     try {
-      const state = await sdk.getState(new PublicKey(options.state));
+      const state = await sdk.getState(
+        options.state ? new PublicKey(options.state) : sdk.findStateAddress()
+      );
       console.log('Current State:');
       console.log(JSON.stringify(state, null, 2));
     } catch (error) {
@@ -5,10 +5,36 @@ import {
     SystemProgram,
     TransactionInstruction,
     Keypair,
+    SYSVAR_CLOCK_PUBKEY,
+    SYSVAR_SLOT_HASHES_PUBKEY,
     sendAndConfirmTransaction,
   } from '@solana/web3.js';
   import { Buffer } from 'buffer';
+  import { createHash } from 'crypto';
+
+  export const TOKEN_PROGRAM_ID = new PublicKey('TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA');
+  export const TOKEN_2022_PROGRAM_ID = new PublicKey('TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb');
+  export const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new PublicKey('BPFLoaderUpgradeab1e11111111111111111111111');
+  export const STATE_VERSION = 1;
+  // sha256("account:MrFreshState")[0..8]
+  export const STATE_DISCRIMINATOR = Buffer.from([136, 246, 179, 45, 91, 128, 163, 124]);
+
+  // Borsh variant index of the Rust EmissionMode
+  export enum EmissionMode {
+    Step = 0,
+    ExponentialDecay = 1,
+  }
+
+  const u64 = (value: number | bigint): Buffer => {
+    const buffer = Buffer.alloc(8);
+    buffer.writeBigUInt64LE(BigInt(value));
+    return buffer;
+  };
   
+  // What CommitMine stores for a secret: sha256(secret || miner)
+  export const commitmentHash = (secret: Buffer, miner: PublicKey): Buffer =>
+    createHash('sha256').update(secret).update(miner.toBuffer()).digest();
+
   export class MrFreshSDK {
     private connection: Connection;
     private programId: PublicKey;
@@ -20,21 +46,73 @@ import {
       this.connection = connection;
       this.programId = typeof programId === 'string' ? new PublicKey(programId) : programId;
     }
+
+    // The global state lives at a PDA derived from the program ID alone
+    findStateAddress(): PublicKey {
+      return PublicKey.findProgramAddressSync([Buffer.from('fresh-state')], this.programId)[0];
+    }
+
+    findMintAuthorityAddress(): PublicKey {
+      return PublicKey.findProgramAddressSync([Buffer.from('mint-authority')], this.programId)[0];
+    }
+
+    findConfigAddress(): PublicKey {
+      return PublicKey.findProgramAddressSync([Buffer.from('fresh-config')], this.programId)[0];
+    }
+
+    findQueuedChangeAddress(id: number): PublicKey {
+      return PublicKey.findProgramAddressSync([Buffer.from('queued-change'), u64(id)], this.programId)[0];
+    }
+
+    findMinerAddress(miner: PublicKey): PublicKey {
+      return PublicKey.findProgramAddressSync([Buffer.from('miner'), miner.toBuffer()], this.programId)[0];
+    }
+
+    // Holds the upgrade authority, the only key allowed to Initialize
+    findProgramDataAddress(): PublicKey {
+      return PublicKey.findProgramAddressSync([this.programId.toBuffer()], BPF_LOADER_UPGRADEABLE_PROGRAM_ID)[0];
+    }
+
+    findCommitmentAddress(miner: PublicKey): PublicKey {
+      return PublicKey.findProgramAddressSync([Buffer.from('mine-commitment'), miner.toBuffer()], this.programId)[0];
+    }
   
+    // proofRequired defaults to true, so mine() and commitMine() need a nonce
+    // from fresh-miner; pass false to also accept plain Mine. A poopChance of 0
+    // leaves poop off
     async initialize(
-      payer: Keypair,
-      stateAccount: Keypair,
+      authority: Keypair,
+      mint: PublicKey,
+      tokenProgram: PublicKey = TOKEN_PROGRAM_ID,
       miningDifficulty: number = 1000,
-      energyBurstDuration: number = 100
+      energyBurstDuration: number = 100,
+      emissionMode: EmissionMode = EmissionMode.Step,
+      proofRequired: boolean = true,
+      poopChance: number = 0,
+      poopConsolation: boolean = false
     ): Promise<string> {
-      const data = Buffer.from([
-        0, // Initialize instruction
-        ...new Uint8Array(new Uint32Array([miningDifficulty]).buffer),
-        ...new Uint8Array(new Uint32Array([energyBurstDuration]).buffer),
+      const data = Buffer.concat([
+        Buffer.from([0]), // Initialize instruction
+        u64(miningDifficulty),
+        u64(energyBurstDuration),
+        Buffer.from([emissionMode]),
+        Buffer.from([proofRequired ? 1 : 0]),
+        u64(poopChance),
+        Buffer.from([poopConsolation ? 1 : 0]),
+        Buffer.from([0]), // No config: use the default economics
       ]);
   
       const instruction = new TransactionInstruction({
-        keys: [{ pubkey: stateAccount.publicKey, isSigner: false, isWritable: true }],
+        keys: [
+          { pubkey: this.findStateAddress(), isSigner: false, isWritable: true },
+          { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+          { pubkey: mint, isSigner: false, isWritable: false },
+          { pubkey: tokenProgram, isSigner: false, isWritable: false },
+          { pubkey: authority.publicKey, isSigner: true, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          { pubkey: this.findConfigAddress(), isSigner: false, isWritable: true },
+          { pubkey: this.findProgramDataAddress(), isSigner: false, isWritable: false },
+        ],
         programId: this.programId,
         data,
       });
@@ -44,23 +122,37 @@ import {
       return await sendAndConfirmTransaction(
         this.connection,
         transaction,
-        [payer, stateAccount],
+        [authority],
         { commitment: 'confirmed' }
       );
     }
   
+    // Pass a nonce from fresh-miner to send MineWithProof instead of Mine.
+    // The challenge it solves is getState().challenge, which every mine
+    // rotates.
     async mine(
       payer: Keypair,
-      stateAccount: PublicKey,
-      minerAccount: Keypair
+      minerAccount: Keypair,
+      minerTokenAccount: PublicKey,
+      nonce?: bigint
     ): Promise<string> {
-      const data = Buffer.from([1]); // Mine instruction
+      const data = nonce === undefined
+        ? Buffer.from([1]) // Mine instruction
+        : Buffer.concat([Buffer.from([14]), u64(nonce)]); // MineWithProof instruction
+      const state = await this.getState();
   
       const instruction = new TransactionInstruction({
         keys: [
-          { pubkey: stateAccount, isSigner: false, isWritable: true },
-          { pubkey: minerAccount.publicKey, isSigner: true, isWritable: false },
+          { pubkey: this.findStateAddress(), isSigner: false, isWritable: true },
+          { pubkey: minerAccount.publicKey, isSigner: true, isWritable: true },
+          { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+          { pubkey: state.mint, isSigner: false, isWritable: true },
+          { pubkey: minerTokenAccount, isSigner: false, isWritable: true },
+          { pubkey: this.findMintAuthorityAddress(), isSigner: false, isWritable: false },
+          { pubkey: state.tokenProgram, isSigner: false, isWritable: false },
+          { pubkey: this.findMinerAddress(minerAccount.publicKey), isSigner: false, isWritable: true },
           { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          { pubkey: this.findConfigAddress(), isSigner: false, isWritable: false },
         ],
         programId: this.programId,
         data,
@@ -76,18 +168,104 @@ import {
       );
     }
   
-    async updateDifficulty(
+    // Commit to a 32-byte secret; reveal it with revealMine a few slots later.
+    // The nonce is only checked while the program requires proofs.
+    async commitMine(
       payer: Keypair,
-      stateAccount: PublicKey,
+      minerAccount: Keypair,
+      secret: Buffer,
+      nonce: bigint = 0n
+    ): Promise<string> {
+      const data = Buffer.concat([
+        Buffer.from([15]), // CommitMine instruction
+        commitmentHash(secret, minerAccount.publicKey),
+        u64(nonce),
+      ]);
+
+      const instruction = new TransactionInstruction({
+        keys: [
+          { pubkey: this.findStateAddress(), isSigner: false, isWritable: false },
+          { pubkey: minerAccount.publicKey, isSigner: true, isWritable: true },
+          { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+          { pubkey: this.findMinerAddress(minerAccount.publicKey), isSigner: false, isWritable: true },
+          { pubkey: this.findCommitmentAddress(minerAccount.publicKey), isSigner: false, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          { pubkey: this.findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data,
+      });
+
+      const transaction = new Transaction().add(instruction);
+
+      return await sendAndConfirmTransaction(
+        this.connection,
+        transaction,
+        [payer, minerAccount],
+        { commitment: 'confirmed' }
+      );
+    }
+
+    async revealMine(
+      payer: Keypair,
+      minerAccount: Keypair,
+      minerTokenAccount: PublicKey,
+      secret: Buffer
+    ): Promise<string> {
+      const data = Buffer.concat([
+        Buffer.from([16]), // RevealMine instruction
+        secret,
+      ]);
+      const state = await this.getState();
+
+      const instruction = new TransactionInstruction({
+        keys: [
+          { pubkey: this.findStateAddress(), isSigner: false, isWritable: true },
+          { pubkey: minerAccount.publicKey, isSigner: true, isWritable: true },
+          { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+          { pubkey: state.mint, isSigner: false, isWritable: true },
+          { pubkey: minerTokenAccount, isSigner: false, isWritable: true },
+          { pubkey: this.findMintAuthorityAddress(), isSigner: false, isWritable: false },
+          { pubkey: state.tokenProgram, isSigner: false, isWritable: false },
+          { pubkey: this.findMinerAddress(minerAccount.publicKey), isSigner: false, isWritable: true },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          { pubkey: this.findConfigAddress(), isSigner: false, isWritable: false },
+          { pubkey: SYSVAR_SLOT_HASHES_PUBKEY, isSigner: false, isWritable: false },
+          { pubkey: this.findCommitmentAddress(minerAccount.publicKey), isSigner: false, isWritable: true },
+        ],
+        programId: this.programId,
+        data,
+      });
+
+      const transaction = new Transaction().add(instruction);
+
+      return await sendAndConfirmTransaction(
+        this.connection,
+        transaction,
+        [payer, minerAccount],
+        { commitment: 'confirmed' }
+      );
+    }
+  
+    async updateDifficulty(
+      authority: Keypair,
       newDifficulty: number
     ): Promise<string> {
-      const data = Buffer.from([
-        2, // UpdateDifficulty instruction
-        ...new Uint8Array(new Uint32Array([newDifficulty]).buffer),
+      const data = Buffer.concat([
+        Buffer.from([2]), // UpdateDifficulty instruction, queued behind the timelock
+        u64(newDifficulty),
       ]);
+      const { nextChangeId } = await this.getState();
   
       const instruction = new TransactionInstruction({
-        keys: [{ pubkey: stateAccount, isSigner: false, isWritable: true }],
+        keys: [
+          { pubkey: this.findStateAddress(), isSigner: false, isWritable: true },
+          { pubkey: authority.publicKey, isSigner: true, isWritable: true },
+          { pubkey: this.findQueuedChangeAddress(nextChangeId), isSigner: false, isWritable: true },
+          { pubkey: authority.publicKey, isSigner: true, isWritable: true },
+          { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
         programId: this.programId,
         data,
       });
@@ -97,12 +275,118 @@ import {
       return await sendAndConfirmTransaction(
         this.connection,
         transaction,
-        [payer],
+        [authority],
+        { commitment: 'confirmed' }
+      );
+    }
+
+    // Queued behind the timelock like updateDifficulty; a chance of 0 disables poop
+    async updatePoopModel(
+      authority: Keypair,
+      poopChance: number,
+      consolation: boolean
+    ): Promise<string> {
+      const data = Buffer.concat([
+        Buffer.from([17]), // UpdatePoopModel instruction
+        u64(poopChance),
+        Buffer.from([consolation ? 1 : 0]),
+      ]);
+      const { nextChangeId } = await this.getState();
+
+      const instruction = new TransactionInstruction({
+        keys: [
+          { pubkey: this.findStateAddress(), isSigner: false, isWritable: true },
+          { pubkey: authority.publicKey, isSigner: true, isWritable: true },
+          { pubkey: this.findQueuedChangeAddress(nextChangeId), isSigner: false, isWritable: true },
+          { pubkey: authority.publicKey, isSigner: true, isWritable: true },
+          { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data,
+      });
+
+      const transaction = new Transaction().add(instruction);
+
+      return await sendAndConfirmTransaction(
+        this.connection,
+        transaction,
+        [authority],
+        { commitment: 'confirmed' }
+      );
+    }
+
+    // Queued behind the timelock like updateDifficulty; when required, Mine is refused
+    async updateProofRequirement(
+      authority: Keypair,
+      required: boolean
+    ): Promise<string> {
+      const data = Buffer.from([18, required ? 1 : 0]); // UpdateProofRequirement instruction
+      const { nextChangeId } = await this.getState();
+
+      const instruction = new TransactionInstruction({
+        keys: [
+          { pubkey: this.findStateAddress(), isSigner: false, isWritable: true },
+          { pubkey: authority.publicKey, isSigner: true, isWritable: true },
+          { pubkey: this.findQueuedChangeAddress(nextChangeId), isSigner: false, isWritable: true },
+          { pubkey: authority.publicKey, isSigner: true, isWritable: true },
+          { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        ],
+        programId: this.programId,
+        data,
+      });
+
+      const transaction = new Transaction().add(instruction);
+
+      return await sendAndConfirmTransaction(
+        this.connection,
+        transaction,
+        [authority],
+        { commitment: 'confirmed' }
+      );
+    }
+
+    async executeQueuedChange(
+      authority: Keypair,
+      changeId: number,
+      rentReceiver: PublicKey = authority.publicKey
+    ): Promise<string> {
+      const queuedChange = await this.connection.getAccountInfo(this.findQueuedChangeAddress(changeId));
+      if (!queuedChange) {
+        throw new Error(`Queued change ${changeId} not found`);
+      }
+
+      const keys = [
+        { pubkey: this.findStateAddress(), isSigner: false, isWritable: true },
+        { pubkey: authority.publicKey, isSigner: true, isWritable: false },
+        { pubkey: this.findQueuedChangeAddress(changeId), isSigner: false, isWritable: true },
+        { pubkey: rentReceiver, isSigner: false, isWritable: true },
+        { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
+      ];
+      // Only a Config change (variant 1, after discriminator, id, effective_at
+      // and rent payer) writes the config account
+      if (queuedChange.data[56] === 1) {
+        keys.push({ pubkey: this.findConfigAddress(), isSigner: false, isWritable: true });
+      }
+      const instruction = new TransactionInstruction({
+        keys,
+        programId: this.programId,
+        data: Buffer.from([9]), // ExecuteQueuedChange instruction
+      });
+
+      const transaction = new Transaction().add(instruction);
+
+      return await sendAndConfirmTransaction(
+        this.connection,
+        transaction,
+        [authority],
         { commitment: 'confirmed' }
       );
     }
   
-    async getState(stateAccount: PublicKey): Promise<{
+    async getState(stateAccount: PublicKey = this.findStateAddress()): Promise<{
+      version: number;
       totalSupply: number;
       miningDifficulty: number;
       lastMiningTimestamp: number;
@@ -110,6 +394,20 @@ import {
       totalTransactions: number;
       lastEnergyBurstSlot: number;
       energyBurstDuration: number;
+      initializationTimestamp: number;
+      mint: PublicKey;
+      tokenProgram: PublicKey;
+      authority: PublicKey;
+      pendingAuthority: PublicKey;
+      isInitialized: boolean;
+      nextChangeId: number;
+      paused: boolean;
+      retargetWindowStart: number;
+      poopChance: number;
+      poopConsolation: boolean;
+      emissionMode: EmissionMode;
+      proofRequired: boolean;
+      challenge: Buffer;
     }> {
       const accountInfo = await this.connection.getAccountInfo(stateAccount);
       if (!accountInfo) {
@@ -118,14 +416,32 @@ import {
   
       // Parse the state data according to your Rust structure
       const data = accountInfo.data;
+      if (!data.subarray(0, 8).equals(STATE_DISCRIMINATOR) || data[8] !== STATE_VERSION) {
+        throw new Error(`State account is not at version ${STATE_VERSION}; run Migrate`);
+      }
       return {
-        totalSupply: Number(data.readBigUInt64LE(0)),
-        miningDifficulty: Number(data.readBigUInt64LE(8)),
-        lastMiningTimestamp: Number(data.readBigInt64LE(16)),
-        totalMiners: Number(data.readBigUInt64LE(24)),
-        totalTransactions: Number(data.readBigUInt64LE(32)),
-        lastEnergyBurstSlot: Number(data.readBigUInt64LE(40)),
-        energyBurstDuration: Number(data.readBigUInt64LE(48)),
+        version: data[8],
+        totalSupply: Number(data.readBigUInt64LE(9)),
+        miningDifficulty: Number(data.readBigUInt64LE(17)),
+        lastMiningTimestamp: Number(data.readBigInt64LE(25)),
+        totalMiners: Number(data.readBigUInt64LE(33)),
+        totalTransactions: Number(data.readBigUInt64LE(41)),
+        lastEnergyBurstSlot: Number(data.readBigUInt64LE(49)),
+        energyBurstDuration: Number(data.readBigUInt64LE(57)),
+        initializationTimestamp: Number(data.readBigInt64LE(65)),
+        mint: new PublicKey(data.subarray(73, 105)),
+        tokenProgram: new PublicKey(data.subarray(106, 138)),
+        authority: new PublicKey(data.subarray(138, 170)),
+        pendingAuthority: new PublicKey(data.subarray(170, 202)),
+        isInitialized: data[202] === 1,
+        nextChangeId: Number(data.readBigUInt64LE(203)),
+        paused: data[211] === 1,
+        retargetWindowStart: Number(data.readBigInt64LE(212)),
+        poopChance: Number(data.readBigUInt64LE(220)),
+        poopConsolation: data[228] === 1,
+        emissionMode: data[229],
+        proofRequired: data[230] === 1,
+        challenge: Buffer.from(data.subarray(231, 263)),
       };
     }
   }
\ No newline at end of file
@@ -1,3 +1,7 @@
+// The on-chain build uses the platform-tools rustc, which predates
+// u64::is_multiple_of, so keep `% n == 0`
+#![allow(clippy::manual_is_multiple_of)]
+
 #[cfg(test)]
 mod tests;
 
@@ -6,26 +10,80 @@ use solana_program::{
     entrypoint,
     entrypoint::ProgramResult,
     msg,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
     pubkey::Pubkey,
     clock::Clock,
+    rent::Rent,
+    slot_hashes::SlotHashes,
     sysvar::{Sysvar, SysvarId},
 };
 use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
+use spl_pod::primitives::{PodBool, PodI64, PodU64};
+use std::cell::{Ref, RefMut};
+use spl_token_2022::{
+    extension::{metadata_pointer, ExtensionType, StateWithExtensions},
+    state::{Account as TokenAccount, Mint},
+};
+use spl_token_metadata_interface::state::TokenMetadata;
+use solana_loader_v3_interface::{get_program_data_address, state::UpgradeableLoaderState};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum FreshError {
     #[error("Cooldown is still active")]
     CooldownActive,
-    #[error("Oh no! Poop discovered!")]
-    PoopDiscovered,
     #[error("Invalid instruction data")]
     InvalidInstruction,
     #[error("Mining difficulty too low")]
     DifficultyTooLow,
     #[error("Maximum supply reached")]
     MaxSupplyReached,
+    #[error("Mint does not match the FRESH mint")]
+    InvalidMint,
+    #[error("Mint authority is not the program PDA")]
+    InvalidMintAuthority,
+    #[error("Mint account is already in use")]
+    MintAlreadyCreated,
+    #[error("Miner must sign the transaction")]
+    MinerMustSign,
+    #[error("Account must be writable")]
+    AccountNotWritable,
+    #[error("Account is not owned by the expected program")]
+    InvalidAccountOwner,
+    #[error("Token account does not belong to the miner")]
+    TokenAccountMismatch,
+    #[error("Signer is not the program authority")]
+    Unauthorized,
+    #[error("Authority has been renounced")]
+    AuthorityRenounced,
+    #[error("State account is already initialized")]
+    AlreadyInitialized,
+    #[error("State account layout is outdated, run Migrate")]
+    StateNeedsMigration,
+    #[error("Account has the wrong type discriminator")]
+    InvalidAccountType,
+    #[error("Config value is out of bounds")]
+    InvalidConfig,
+    #[error("Queued change is still timelocked")]
+    TimelockActive,
+    #[error("Mining is paused")]
+    Paused,
+    #[error("Multisig threshold or signers are invalid")]
+    InvalidMultisig,
+    #[error("Proof of work does not meet the difficulty target")]
+    InvalidProof,
+    #[error("Miner already has a commitment waiting to be revealed")]
+    CommitmentPending,
+    #[error("Commitment cannot be revealed yet")]
+    RevealTooEarly,
+    #[error("Commitment expired before it was revealed")]
+    CommitmentExpired,
+    #[error("Secret does not match the commitment")]
+    InvalidReveal,
+    #[error("Mining requires a proof of work")]
+    ProofRequired,
 }
 
 impl From<FreshError> for ProgramError {
@@ -34,20 +92,122 @@ impl From<FreshError> for ProgramError {
     }
 }
 
-// Original constants
+// Original constants, now the defaults written to the Config account
 pub const MINING_COOLDOWN: i64 = 1800;        // 30 minutes
 pub const ENERGY_BURST_BONUS: u64 = 150;      // 50% bonus
 pub const LUCKY_PURR_CHANCE: u64 = 100;       // 1% chance
 pub const LUCKY_PURR_BONUS: u64 = 110;        // 10% bonus
 pub const MIN_DIFFICULTY: u64 = 100;          // Minimum mining difficulty
+pub const ENERGY_BURST_INTERVAL: u64 = 41;    // 1 in 41 mines rolls a burst
+pub const MIN_POOP_CHANCE: u64 = 2;            // Never let every mine fail
 
 // New supply and halving constants
 pub const HALVING_INTERVAL: i64 = 31_536_000;  // 365 days in seconds
 pub const MAX_SUPPLY: u64 = 50_000_000_000_000_000;  // 50 million with 9 decimals
 pub const INITIAL_BASE_REWARD: u64 = 10_000_000;     // Initial mining reward
 
+// Mint constants
+pub const FRESH_DECIMALS: u8 = 9;              // Matches MAX_SUPPLY scaling
+pub const STATE_SEED: &[u8] = b"fresh-state";
+pub const MINT_SEED: &[u8] = b"fresh-mint";
+pub const MINT_AUTHORITY_SEED: &[u8] = b"mint-authority";
+pub const MINER_SEED: &[u8] = b"miner";
+pub const CONFIG_SEED: &[u8] = b"fresh-config";
+pub const QUEUED_CHANGE_SEED: &[u8] = b"queued-change";
+pub const COMMITMENT_SEED: &[u8] = b"mine-commitment";
+
+pub const MAX_MULTISIG_SIGNERS: usize = 11;
+
+// Difficulty retargeting
+pub const RETARGET_WINDOW: u64 = 100;          // Mines per retarget window
+pub const TARGET_MINE_INTERVAL: i64 = 60;      // Target seconds between mines
+pub const MAX_RETARGET_FACTOR: u64 = 4;        // Max change per window, either way
+
+// Keep the energy burst and lucky purr rolls independent
+pub const ENERGY_BURST_SALT: &[u8] = b"energy-burst";
+pub const LUCKY_PURR_SALT: &[u8] = b"lucky-purr";
+pub const POOP_SALT: &[u8] = b"poop";
+
+// Commit-reveal mining
+pub const REVEAL_DELAY_SLOTS: u64 = 4;         // Bonus slot hash comes this long after the commit
+pub const COMMIT_EXPIRY_SLOTS: u64 = 150;      // Well inside the 512 slots SlotHashes keeps
+
+// Parameter changes wait this long between queueing and execution
+pub const TIMELOCK_DELAY: i64 = 172_800;       // 48 hours
+
+// Config bounds
+pub const MAX_MINING_COOLDOWN: i64 = 604_800;  // 7 days
+pub const MAX_BONUS: u64 = 1000;               // 10x, bonuses are percentages
+pub const MIN_HALVING_INTERVAL: i64 = 86_400;  // 1 day
+pub const MAX_HALVING_INTERVAL: i64 = 10 * HALVING_INTERVAL;  // 10 years
+pub const MAX_BASE_REWARD: u64 = 100 * INITIAL_BASE_REWARD;   // 100x the default
+
+// Bump whenever the MrFreshState layout changes and teach Migrate the upgrade
+pub const STATE_VERSION: u8 = 1;
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// A program-owned account type. Accounts are stored as an 8-byte
+/// discriminator (first bytes of sha256("account:<TypeName>")) followed by
+/// the Borsh-serialized body, zero-padded up to LEN for variable-size bodies.
+pub trait FreshAccount: BorshSerialize + BorshDeserialize {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN];
+    /// Full account size, discriminator included
+    const LEN: usize;
+}
+
+/// Decodes raw account data of the expected type; also usable off-chain.
+pub fn unpack_account<T: FreshAccount>(data: &[u8]) -> Result<T, ProgramError> {
+    if data.len() != T::LEN || data[..DISCRIMINATOR_LEN] != T::DISCRIMINATOR {
+        return Err(FreshError::InvalidAccountType.into());
+    }
+    Ok(T::deserialize(&mut &data[DISCRIMINATOR_LEN..])?)
+}
+
+pub fn load_account<T: FreshAccount>(program_id: &Pubkey, account: &AccountInfo) -> Result<T, ProgramError> {
+    if account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+    unpack_account(&account.data.borrow()).inspect_err(|_| {
+        msg!("Account {} has an unexpected type", account.key);
+    })
+}
+
+fn check_view_data<T: FreshAccount>(program_id: &Pubkey, account: &AccountInfo, data: &[u8]) -> ProgramResult {
+    if account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+    if data.len() != T::LEN || data[..DISCRIMINATOR_LEN] != T::DISCRIMINATOR {
+        msg!("Account {} has an unexpected type", account.key);
+        return Err(FreshError::InvalidAccountType.into());
+    }
+    Ok(())
+}
+
+/// Zero-copy counterpart of `load_account` for the Mine hot path. `V` must
+/// mirror the Borsh layout of `T` byte for byte, discriminator included.
+fn load_view<'a, T: FreshAccount, V: Pod>(program_id: &Pubkey, account: &'a AccountInfo) -> Result<Ref<'a, V>, ProgramError> {
+    let data = account.data.borrow();
+    check_view_data::<T>(program_id, account, &data)?;
+    Ok(Ref::map(data, |data| bytemuck::from_bytes(data)))
+}
+
+/// Like `load_view`, but writes go straight through to the account data.
+fn load_view_mut<'a, T: FreshAccount, V: Pod>(program_id: &Pubkey, account: &'a AccountInfo) -> Result<RefMut<'a, V>, ProgramError> {
+    let data = account.data.borrow_mut();
+    check_view_data::<T>(program_id, account, &data)?;
+    Ok(RefMut::map(data, |data| bytemuck::from_bytes_mut(data)))
+}
+
+pub fn store_account<T: FreshAccount>(account: &AccountInfo, value: &T) -> ProgramResult {
+    let mut data = account.data.borrow_mut();
+    data[..DISCRIMINATOR_LEN].copy_from_slice(&T::DISCRIMINATOR);
+    value.serialize(&mut &mut data[DISCRIMINATOR_LEN..])?;
+    Ok(())
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 pub struct MrFreshState {
+    pub version: u8,
     pub total_supply: u64,
     pub mining_difficulty: u64,
     pub last_mining_timestamp: i64,
@@ -56,6 +216,402 @@ pub struct MrFreshState {
     pub last_energy_burst_slot: u64,
     pub energy_burst_duration: u64,
     pub initialization_timestamp: i64,    // New field for tracking program start
+    pub mint: Pubkey,
+    pub mint_authority_bump: u8,
+    pub token_program: Pubkey,            // SPL Token or Token-2022
+    pub authority: Pubkey,                // Admin allowed to tune parameters
+    pub pending_authority: Pubkey,        // Proposed successor, default when none
+    pub is_initialized: bool,
+    pub next_change_id: u64,              // Seeds the next QueuedChange PDA
+    pub paused: bool,                     // Emergency stop for Mine
+    pub retarget_window_start: i64,       // When the current retarget window opened
+    pub poop_chance: u64,                 // 1 in N mines finds poop, 0 disables
+    pub poop_consolation: bool,           // Halve the nap after a poop
+    pub emission_mode: EmissionMode,      // Fixed at Initialize
+    pub proof_required: bool,             // Refuse Mine, so every reward costs a proof
+    pub challenge: [u8; 32],              // Proof-of-work input, rotated by every mine
+}
+
+impl FreshAccount for MrFreshState {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [136, 246, 179, 45, 91, 128, 163, 124];
+    const LEN: usize = DISCRIMINATOR_LEN + 1 + 8 * 8 + 32 + 1 + 32 + 32 + 32 + 1 + 8 + 1 + 8 + 8 + 1 + 1 + 1 + 32;
+}
+
+/// Zero-copy view of a current-version MrFreshState account, used by the Mine
+/// hot path to skip a full Borsh round trip. Every field has alignment 1 so the
+/// struct lines up byte for byte with the Borsh encoding.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct MrFreshStateView {
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub version: u8,
+    pub total_supply: PodU64,
+    pub mining_difficulty: PodU64,
+    pub last_mining_timestamp: PodI64,
+    pub total_miners: PodU64,
+    pub total_transactions: PodU64,
+    pub last_energy_burst_slot: PodU64,
+    pub energy_burst_duration: PodU64,
+    pub initialization_timestamp: PodI64,
+    pub mint: Pubkey,
+    pub mint_authority_bump: u8,
+    pub token_program: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub is_initialized: PodBool,
+    pub next_change_id: PodU64,
+    pub paused: PodBool,
+    pub retarget_window_start: PodI64,
+    pub poop_chance: PodU64,
+    pub poop_consolation: PodBool,
+    pub emission_mode: u8,
+    pub proof_required: PodBool,
+    pub challenge: [u8; 32],
+}
+
+// Keep the view in lockstep with the Borsh layout
+const _: () = assert!(std::mem::size_of::<MrFreshStateView>() == MrFreshState::LEN);
+
+impl MrFreshStateView {
+    pub fn emission_mode(&self) -> EmissionMode {
+        // Borsh tags variants by index and only Initialize writes the mode
+        match self.emission_mode {
+            1 => EmissionMode::ExponentialDecay,
+            _ => EmissionMode::Step,
+        }
+    }
+}
+
+impl From<&MrFreshState> for MrFreshStateView {
+    fn from(state: &MrFreshState) -> Self {
+        MrFreshStateView {
+            discriminator: MrFreshState::DISCRIMINATOR,
+            version: state.version,
+            total_supply: state.total_supply.into(),
+            mining_difficulty: state.mining_difficulty.into(),
+            last_mining_timestamp: state.last_mining_timestamp.into(),
+            total_miners: state.total_miners.into(),
+            total_transactions: state.total_transactions.into(),
+            last_energy_burst_slot: state.last_energy_burst_slot.into(),
+            energy_burst_duration: state.energy_burst_duration.into(),
+            initialization_timestamp: state.initialization_timestamp.into(),
+            mint: state.mint,
+            mint_authority_bump: state.mint_authority_bump,
+            token_program: state.token_program,
+            authority: state.authority,
+            pending_authority: state.pending_authority,
+            is_initialized: state.is_initialized.into(),
+            next_change_id: state.next_change_id.into(),
+            paused: state.paused.into(),
+            retarget_window_start: state.retarget_window_start.into(),
+            poop_chance: state.poop_chance.into(),
+            poop_consolation: state.poop_consolation.into(),
+            emission_mode: state.emission_mode as u8,
+            proof_required: state.proof_required.into(),
+            challenge: state.challenge,
+        }
+    }
+}
+
+impl From<&MrFreshStateView> for MrFreshState {
+    fn from(view: &MrFreshStateView) -> Self {
+        MrFreshState {
+            version: view.version,
+            total_supply: view.total_supply.into(),
+            mining_difficulty: view.mining_difficulty.into(),
+            last_mining_timestamp: view.last_mining_timestamp.into(),
+            total_miners: view.total_miners.into(),
+            total_transactions: view.total_transactions.into(),
+            last_energy_burst_slot: view.last_energy_burst_slot.into(),
+            energy_burst_duration: view.energy_burst_duration.into(),
+            initialization_timestamp: view.initialization_timestamp.into(),
+            mint: view.mint,
+            mint_authority_bump: view.mint_authority_bump,
+            token_program: view.token_program,
+            authority: view.authority,
+            pending_authority: view.pending_authority,
+            is_initialized: view.is_initialized.into(),
+            next_change_id: view.next_change_id.into(),
+            paused: view.paused.into(),
+            retarget_window_start: view.retarget_window_start.into(),
+            poop_chance: view.poop_chance.into(),
+            poop_consolation: view.poop_consolation.into(),
+            emission_mode: view.emission_mode(),
+            proof_required: view.proof_required.into(),
+            challenge: view.challenge,
+        }
+    }
+}
+
+/// Economics that used to be compile-time constants. Initialize writes the
+/// deployer's choice or the defaults, and the authority can tune them with
+/// UpdateConfig.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct FreshConfig {
+    pub mining_cooldown: i64,
+    pub energy_burst_bonus: u64,
+    pub energy_burst_interval: u64,
+    pub lucky_purr_chance: u64,
+    pub lucky_purr_bonus: u64,
+    pub halving_interval: i64,
+    pub max_supply: u64,
+    pub initial_base_reward: u64,
+}
+
+impl FreshAccount for FreshConfig {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [240, 186, 86, 47, 102, 113, 149, 77];
+    const LEN: usize = DISCRIMINATOR_LEN + 8 * 8;
+}
+
+impl Default for FreshConfig {
+    fn default() -> Self {
+        FreshConfig {
+            mining_cooldown: MINING_COOLDOWN,
+            energy_burst_bonus: ENERGY_BURST_BONUS,
+            energy_burst_interval: ENERGY_BURST_INTERVAL,
+            lucky_purr_chance: LUCKY_PURR_CHANCE,
+            lucky_purr_bonus: LUCKY_PURR_BONUS,
+            halving_interval: HALVING_INTERVAL,
+            max_supply: MAX_SUPPLY,
+            initial_base_reward: INITIAL_BASE_REWARD,
+        }
+    }
+}
+
+/// Zero-copy view of a FreshConfig account, see MrFreshStateView.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct FreshConfigView {
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub mining_cooldown: PodI64,
+    pub energy_burst_bonus: PodU64,
+    pub energy_burst_interval: PodU64,
+    pub lucky_purr_chance: PodU64,
+    pub lucky_purr_bonus: PodU64,
+    pub halving_interval: PodI64,
+    pub max_supply: PodU64,
+    pub initial_base_reward: PodU64,
+}
+
+const _: () = assert!(std::mem::size_of::<FreshConfigView>() == FreshConfig::LEN);
+
+impl From<&FreshConfigView> for FreshConfig {
+    fn from(view: &FreshConfigView) -> Self {
+        FreshConfig {
+            mining_cooldown: view.mining_cooldown.into(),
+            energy_burst_bonus: view.energy_burst_bonus.into(),
+            energy_burst_interval: view.energy_burst_interval.into(),
+            lucky_purr_chance: view.lucky_purr_chance.into(),
+            lucky_purr_bonus: view.lucky_purr_bonus.into(),
+            halving_interval: view.halving_interval.into(),
+            max_supply: view.max_supply.into(),
+            initial_base_reward: view.initial_base_reward.into(),
+        }
+    }
+}
+
+impl FreshConfig {
+    /// Rejects values that would break mining (zero divisors, bonuses that cut
+    /// rewards), lift supply past the MAX_SUPPLY hard cap or cap it below what
+    /// has already been minted.
+    pub fn validate(&self, total_supply: u64) -> ProgramResult {
+        if !(0..=MAX_MINING_COOLDOWN).contains(&self.mining_cooldown) {
+            msg!("Cooldown must be between 0 and {} seconds", MAX_MINING_COOLDOWN);
+            return Err(FreshError::InvalidConfig.into());
+        }
+        if !(100..=MAX_BONUS).contains(&self.energy_burst_bonus) || !(100..=MAX_BONUS).contains(&self.lucky_purr_bonus) {
+            msg!("Bonuses must be between 100 and {} percent", MAX_BONUS);
+            return Err(FreshError::InvalidConfig.into());
+        }
+        if self.energy_burst_interval == 0 || self.lucky_purr_chance == 0 {
+            msg!("Intervals and chances must be positive");
+            return Err(FreshError::InvalidConfig.into());
+        }
+        if !(MIN_HALVING_INTERVAL..=MAX_HALVING_INTERVAL).contains(&self.halving_interval) {
+            msg!("Halving interval must be between {} and {} seconds", MIN_HALVING_INTERVAL, MAX_HALVING_INTERVAL);
+            return Err(FreshError::InvalidConfig.into());
+        }
+        if self.max_supply > MAX_SUPPLY {
+            msg!("Max supply cannot exceed the {} hard cap", MAX_SUPPLY);
+            return Err(FreshError::InvalidConfig.into());
+        }
+        if self.max_supply < total_supply {
+            msg!("Max supply cannot drop below the {} already minted", total_supply);
+            return Err(FreshError::InvalidConfig.into());
+        }
+        if self.initial_base_reward == 0 || self.initial_base_reward > MAX_BASE_REWARD.min(self.max_supply) {
+            msg!("Base reward must be positive and at most {}", MAX_BASE_REWARD.min(self.max_supply));
+            return Err(FreshError::InvalidConfig.into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum ParameterChange {
+    Difficulty {
+        new_difficulty: u64,
+    },
+    Config {
+        config: FreshConfig,
+    },
+    PoopModel {
+        poop_chance: u64,
+        consolation: bool,
+    },
+    ProofRequirement {
+        required: bool,
+    },
+}
+
+/// A parameter change waiting out the timelock, stored at the PDA
+/// [QUEUED_CHANGE_SEED, id]. Closed when executed or cancelled.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct QueuedChange {
+    pub id: u64,
+    pub effective_at: i64,
+    pub rent_payer: Pubkey,               // Refunded when the account closes
+    pub change: ParameterChange,
+}
+
+impl FreshAccount for QueuedChange {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [178, 209, 212, 240, 49, 65, 50, 3];
+    // Sized for the largest change
+    const LEN: usize = DISCRIMINATOR_LEN + 8 + 8 + 32 + 1 + (FreshConfig::LEN - DISCRIMINATOR_LEN);
+}
+
+/// An M-of-N signer set that can hold the program authority. Admin
+/// instructions pass the multisig as the authority account and list its
+/// signers after the instruction's own accounts.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct Multisig {
+    pub threshold: u8,
+    pub signer_count: u8,
+    pub signers: [Pubkey; MAX_MULTISIG_SIGNERS],
+}
+
+impl FreshAccount for Multisig {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [224, 116, 121, 186, 68, 161, 79, 236];
+    const LEN: usize = DISCRIMINATOR_LEN + 1 + 1 + 32 * MAX_MULTISIG_SIGNERS;
+}
+
+impl Multisig {
+    pub fn signers(&self) -> &[Pubkey] {
+        &self.signers[..self.signer_count as usize]
+    }
+}
+
+/// A CommitMine waiting for its RevealMine, stored at the PDA
+/// [COMMITMENT_SEED, miner]. The commit spends the miner's turn, so a
+/// commitment left to expire forfeits that mine; the next CommitMine
+/// replaces it.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct MineCommitment {
+    pub miner: Pubkey,
+    pub commitment: [u8; 32],             // commitment_hash of the secret
+    pub commit_slot: u64,
+    pub mine_count: u64,                  // Any mine after the commit voids it
+}
+
+impl FreshAccount for MineCommitment {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [205, 58, 133, 87, 19, 105, 201, 81];
+    const LEN: usize = DISCRIMINATOR_LEN + 32 + 32 + 8 + 8;
+}
+
+impl MineCommitment {
+    /// The slot whose hash, mixed with the secret, decides the bonuses
+    pub fn target_slot(&self) -> u64 {
+        self.commit_slot.saturating_add(REVEAL_DELAY_SLOTS)
+    }
+
+    pub fn is_expired(&self, slot: u64) -> bool {
+        slot > self.commit_slot.saturating_add(COMMIT_EXPIRY_SLOTS)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct MinerAccount {
+    pub miner: Pubkey,
+    pub last_mining_timestamp: i64,       // NEVER_MINED until the first mine or commit
+    pub total_rewards: u64,
+    pub mine_count: u64,
+    pub bump: u8,
+}
+
+impl FreshAccount for MinerAccount {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [232, 196, 79, 139, 222, 213, 161, 99];
+    const LEN: usize = DISCRIMINATOR_LEN + 32 + 8 + 8 + 8 + 1;
+}
+
+impl MinerAccount {
+    pub const NEVER_MINED: i64 = i64::MIN;
+}
+
+/// Zero-copy view of a MinerAccount, see MrFreshStateView.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct MinerAccountView {
+    pub discriminator: [u8; DISCRIMINATOR_LEN],
+    pub miner: Pubkey,
+    pub last_mining_timestamp: PodI64,
+    pub total_rewards: PodU64,
+    pub mine_count: PodU64,
+    pub bump: u8,
+}
+
+const _: () = assert!(std::mem::size_of::<MinerAccountView>() == MinerAccount::LEN);
+
+impl From<&MinerAccount> for MinerAccountView {
+    fn from(record: &MinerAccount) -> Self {
+        MinerAccountView {
+            discriminator: MinerAccount::DISCRIMINATOR,
+            miner: record.miner,
+            last_mining_timestamp: record.last_mining_timestamp.into(),
+            total_rewards: record.total_rewards.into(),
+            mine_count: record.mine_count.into(),
+            bump: record.bump,
+        }
+    }
+}
+
+impl From<&MinerAccountView> for MinerAccount {
+    fn from(view: &MinerAccountView) -> Self {
+        MinerAccount {
+            miner: view.miner,
+            last_mining_timestamp: view.last_mining_timestamp.into(),
+            total_rewards: view.total_rewards.into(),
+            mine_count: view.mine_count.into(),
+            bump: view.bump,
+        }
+    }
+}
+
+pub fn find_miner_address(program_id: &Pubkey, miner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MINER_SEED, miner.as_ref()], program_id)
+}
+
+pub fn find_state_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STATE_SEED], program_id)
+}
+
+pub fn find_mint_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MINT_SEED], program_id)
+}
+
+pub fn find_mint_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MINT_AUTHORITY_SEED], program_id)
+}
+
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+}
+
+pub fn find_queued_change_address(program_id: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[QUEUED_CHANGE_SEED, &id.to_le_bytes()], program_id)
+}
+
+pub fn find_commitment_address(program_id: &Pubkey, miner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[COMMITMENT_SEED, miner.as_ref()], program_id)
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
@@ -63,48 +619,276 @@ pub enum MrFreshInstruction {
     Initialize {
         mining_difficulty: u64,
         energy_burst_duration: u64,
+        emission_mode: EmissionMode,
+        proof_required: bool,
+        poop_chance: u64,                 // 0 leaves poop off
+        poop_consolation: bool,
+        config: Option<FreshConfig>,      // None for the defaults
     },
     Mine,
     UpdateDifficulty {
         new_difficulty: u64,
     },
+    CreateMint {
+        name: String,
+        symbol: String,
+        uri: String,
+    },
+    ProposeAuthority {
+        new_authority: Pubkey,
+    },
+    AcceptAuthority,
+    RenounceAuthority,
+    Migrate,
+    UpdateConfig {
+        config: FreshConfig,
+    },
+    ExecuteQueuedChange,
+    CancelQueuedChange,
+    Pause,
+    Unpause,
+    CreateMultisig {
+        threshold: u8,
+        signers: Vec<Pubkey>,
+    },
+    MineWithProof {
+        nonce: u64,
+    },
+    CommitMine {
+        hash: [u8; 32],
+        nonce: u64,                       // Checked only while proofs are required
+    },
+    RevealMine {
+        secret: [u8; 32],
+    },
+    UpdatePoopModel {
+        poop_chance: u64,
+        consolation: bool,
+    },
+    UpdateProofRequirement {
+        required: bool,
+    },
+}
+
+// How a mine proves itself and where its bonus rolls come from
+enum MineMode {
+    Plain,
+    Proof { nonce: u64 },
+    Reveal { secret: [u8; 32] },
 }
 
 entrypoint!(process_instruction);
 
-fn calculate_mining_reward(state: &MrFreshState, current_time: i64) -> Result<u64, ProgramError> {
-    // Check if max supply reached
-    if state.total_supply >= MAX_SUPPLY {
-        msg!("Maximum supply of 50 million FRESH tokens reached!");
-        return Err(FreshError::MaxSupplyReached.into());
+/// Bitcoin-style retarget: scales difficulty by how much faster or slower the
+/// last window of mines came in than the target, by at most
+/// MAX_RETARGET_FACTOR either way and never below MIN_DIFFICULTY.
+pub fn retarget_difficulty(current_difficulty: u64, actual_timespan: i64) -> u64 {
+    let target_timespan = RETARGET_WINDOW as i64 * TARGET_MINE_INTERVAL;
+    let actual_timespan = actual_timespan.clamp(
+        target_timespan / MAX_RETARGET_FACTOR as i64,
+        target_timespan * MAX_RETARGET_FACTOR as i64,
+    );
+    let new_difficulty = current_difficulty as u128 * target_timespan as u128 / actual_timespan as u128;
+    u64::try_from(new_difficulty).unwrap_or(u64::MAX).max(MIN_DIFFICULTY)
+}
+
+/// The challenge after a mine landed in `slot`. Every mine moves it, so a
+/// nonce only holds until somebody mines, and the slot keeps anyone from
+/// solving the next one ahead of time.
+pub fn next_challenge(challenge: &[u8; 32], slot: u64) -> [u8; 32] {
+    solana_program::hash::hashv(&[challenge, &slot.to_le_bytes()]).to_bytes()
+}
+
+/// The hash a MineWithProof or CommitMine nonce is judged on.
+pub fn proof_hash(challenge: &[u8; 32], miner: &Pubkey, nonce: u64) -> [u8; 32] {
+    solana_program::hash::hashv(&[challenge, miner.as_ref(), &nonce.to_le_bytes()]).to_bytes()
+}
+
+/// A proof passes when its leading 128 bits, read big-endian, fall at or
+/// under u128::MAX / difficulty, so a valid nonce takes `difficulty` hashes
+/// on average.
+pub fn meets_difficulty(hash: &[u8; 32], difficulty: u64) -> bool {
+    let mut leading = [0u8; 16];
+    leading.copy_from_slice(&hash[..16]);
+    u128::from_be_bytes(leading) <= u128::MAX / u128::from(difficulty.max(1))
+}
+
+/// A roll for one revealed mine: the reveal seed mixed with the miner and the
+/// commit slot, salted so the energy burst, lucky purr and poop rolls stay
+/// independent. Only RevealMine rolls. Anything Mine or MineWithProof could
+/// roll from is public before the transaction lands, and a wrapping program
+/// could abort a losing roll, so they are paid `single_transaction_reward`.
+pub fn bonus_roll(seed: &[u8; 32], miner: &Pubkey, counter: u64, salt: &[u8]) -> u64 {
+    let hash = solana_program::hash::hashv(&[seed, miner.as_ref(), &counter.to_le_bytes(), salt]);
+    let mut roll = [0u8; 8];
+    roll.copy_from_slice(&hash.as_ref()[..8]);
+    u64::from_le_bytes(roll)
+}
+
+// A reward with a bonus of `bonus` percent that lands 1 in `odds` times
+fn expected_bonus(reward: u64, bonus: u64, odds: u64) -> u64 {
+    reward.saturating_add(reward.saturating_mul(bonus.saturating_sub(100)) / odds.saturating_mul(100).max(1))
+}
+
+/// What Mine and MineWithProof pay for a base reward: the expected value of
+/// the poop, energy burst and lucky purr rolls a reveal would make. While a
+/// revealed burst's duration runs no burst can land, so none is expected.
+pub fn single_transaction_reward(reward: u64, config: &FreshConfig, poop_chance: u64, burst_ready: bool) -> u64 {
+    // A poop chance of 0 means poop is off
+    let reward = reward.saturating_sub(reward.checked_div(poop_chance).unwrap_or(0));
+    let reward = if burst_ready {
+        expected_bonus(reward, config.energy_burst_bonus, config.energy_burst_interval)
+    } else {
+        reward
+    };
+    expected_bonus(reward, config.lucky_purr_bonus, config.lucky_purr_chance)
+}
+
+/// What a miner passes to CommitMine for a secret they will later reveal.
+pub fn commitment_hash(secret: &[u8; 32], miner: &Pubkey) -> [u8; 32] {
+    solana_program::hash::hashv(&[secret, miner.as_ref()]).to_bytes()
+}
+
+/// Replaces the slot hash in a revealed mine's bonus rolls. Neither the
+/// miner, who can't see the target slot hash at commit time, nor the leader
+/// of the target slot, who can't see the secret, controls the result.
+pub fn reveal_seed(secret: &[u8; 32], target_slot_hash: &[u8; 32]) -> [u8; 32] {
+    solana_program::hash::hashv(&[secret, target_slot_hash]).to_bytes()
+}
+
+/// How the base reward falls over time, chosen once at Initialize.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmissionMode {
+    /// Halves in one step every halving interval
+    Step,
+    /// Decays continuously with the halving interval as its half-life, so
+    /// there is no cliff to rush before
+    ExponentialDecay,
+}
+
+// 2^(-2^-i) in Q64 for i = 1..=32
+const HALF_LIFE_FACTORS: [u64; 32] = [
+    0xb504f333f9de6484, 0xd744fccad69d6af4, 0xeac0c6e7dd24392e, 0xf5257d152486cc2c,
+    0xfa83b2db722a033a, 0xfd3e0c0cf486c174, 0xfe9e115c7b8f884b, 0xff4ecb59511ec8a5,
+    0xffa756521c8daed1, 0xffd3a751c0f7e10b, 0xffe9d2b2f7db2755, 0xfff4e91bff1b8c3d,
+    0xfffa747ea0040664, 0xfffd3a3b7814eb53, 0xfffe9d1cc60ddab1, 0xffff4e8e25879bfa,
+    0xffffa7470363f451, 0xffffd3a37dda0313, 0xffffe9d1bdf703ae, 0xfffff4e8debe025e,
+    0xfffffa746f4fa150, 0xfffffd3a37a3f8b0, 0xfffffe9d1bd1065a, 0xffffff4e8de845ad,
+    0xffffffa746f41376, 0xffffffd3a37a05e3, 0xffffffe9d1bd01fb, 0xfffffff4e8de80c0,
+    0xfffffffa746f4050, 0xfffffffd3a37a024, 0xfffffffe9d1bd011, 0xffffffff4e8de808,
+];
+
+/// 2^(-elapsed / half_life) in Q64 for elapsed < half_life: the exponent to
+/// 32 binary places, times one table factor per set bit.
+fn decay_factor(elapsed: u64, half_life: u64) -> u128 {
+    let exponent = ((elapsed as u128) << 32) / half_life as u128;
+    let mut factor = 1u128 << 64;
+    for (i, half_life_factor) in HALF_LIFE_FACTORS.iter().enumerate() {
+        if exponent & (1 << (31 - i)) != 0 {
+            factor = (factor * *half_life_factor as u128) >> 64;
+        }
     }
+    factor
+}
 
-    // Calculate time since initialization
-    let time_since_start = current_time.saturating_sub(state.initialization_timestamp);
-    let halving_epoch = time_since_start / HALVING_INTERVAL;
-    
-    // Calculate current base reward with halving
-    let mut current_base_reward = INITIAL_BASE_REWARD;
-    for _ in 0..halving_epoch {
-        current_base_reward = current_base_reward.saturating_div(2);
+/// Everything the emission schedule depends on, gathered from the state and
+/// config so the schedule can be evaluated anywhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmissionSchedule {
+    pub mode: EmissionMode,
+    pub initialization_timestamp: i64,
+    pub halving_interval: i64,
+    pub initial_base_reward: u64,
+    pub max_supply: u64,
+    pub total_supply: u64,
+}
+
+/// Where the schedule stands at a given time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmissionInfo {
+    pub epoch: u64,                       // Halvings (or half-lives) so far
+    pub base_reward: u64,                 // After halving or decay, before difficulty
+    pub reward: u64,                      // What a mine pays before bonuses and the supply cap
+    pub next_halving_at: i64,
+    pub remaining_supply: u64,
+}
+
+impl EmissionSchedule {
+    pub fn new(mode: EmissionMode, initialization_timestamp: i64, total_supply: u64, config: &FreshConfig) -> Self {
+        Self {
+            mode,
+            initialization_timestamp,
+            halving_interval: config.halving_interval,
+            initial_base_reward: config.initial_base_reward,
+            max_supply: config.max_supply,
+            total_supply,
+        }
     }
 
-    // If base reward has been reduced to zero due to halvings, return error
-    if current_base_reward == 0 {
-        msg!("Mining rewards have reached minimum threshold");
-        return Err(FreshError::MaxSupplyReached.into());
+    /// Evaluates the schedule in constant time: the base reward halves once
+    /// per elapsed halving interval, as a shift, and under exponential decay
+    /// also falls through the current interval in fixed point.
+    pub fn emission_at(&self, timestamp: i64, difficulty: u64) -> EmissionInfo {
+        let halving_interval = self.halving_interval.max(1);
+        let time_since_start = timestamp.saturating_sub(self.initialization_timestamp).max(0);
+        let epoch = (time_since_start / halving_interval) as u64;
+        let scaled_reward = match self.mode {
+            EmissionMode::Step => (self.initial_base_reward as u128) << 64,
+            EmissionMode::ExponentialDecay => {
+                let into_epoch = (time_since_start % halving_interval) as u64;
+                self.initial_base_reward as u128 * decay_factor(into_epoch, halving_interval as u64)
+            }
+        };
+        let base_reward = if epoch < u64::BITS as u64 { (scaled_reward >> 64 >> epoch) as u64 } else { 0 };
+        let next_halving_at = self.initialization_timestamp
+            .saturating_add((epoch as i64).saturating_add(1).saturating_mul(halving_interval));
+        EmissionInfo {
+            epoch,
+            base_reward,
+            reward: base_reward / difficulty.max(1),
+            next_halving_at,
+            remaining_supply: self.max_supply.saturating_sub(self.total_supply),
+        }
     }
+}
 
-    // Calculate final reward based on difficulty
-    let reward = current_base_reward.saturating_div(state.mining_difficulty);
-    
-    msg!("Debug: Reward calculation:");
-    msg!("  Time since start: {} seconds", time_since_start);
-    msg!("  Current halving epoch: {}", halving_epoch);
-    msg!("  Current base reward: {}", current_base_reward);
-    msg!("  Final reward after difficulty: {}", reward);
-    
-    Ok(reward)
+/// The published schedule: the default economics under Step emission, with
+/// `timestamp` counted in seconds from initialization and nothing mined yet.
+/// A deployment with its own config or mode evaluates an `EmissionSchedule`.
+pub fn emission_at(timestamp: i64, difficulty: u64) -> EmissionInfo {
+    EmissionSchedule::new(EmissionMode::Step, 0, 0, &FreshConfig::default()).emission_at(timestamp, difficulty)
+}
+
+/// Reward for a mine at `current_time` before bonuses. Free of logging so
+/// off-chain tools such as fresh-miner can predict what Mine will pay.
+pub fn expected_mining_reward(
+    state: &MrFreshStateView,
+    config: &FreshConfig,
+    current_time: i64,
+) -> Result<u64, FreshError> {
+    let emission = EmissionSchedule::new(
+        state.emission_mode(),
+        state.initialization_timestamp.into(),
+        state.total_supply.into(),
+        config,
+    )
+    .emission_at(current_time, state.mining_difficulty.into());
+    // Emission ends at the supply cap or once halving wears the reward to zero
+    if emission.remaining_supply == 0 || emission.base_reward == 0 {
+        return Err(FreshError::MaxSupplyReached);
+    }
+    Ok(emission.reward)
+}
+
+fn calculate_mining_reward(
+    state: &MrFreshStateView,
+    config: &FreshConfig,
+    current_time: i64,
+) -> Result<u64, ProgramError> {
+    expected_mining_reward(state, config, current_time).map_err(|e| {
+        msg!("Emission has ended at {} of {} FRESH tokens", u64::from(state.total_supply), config.max_supply);
+        e.into()
+    })
 }
 
 pub fn process_instruction(
@@ -118,41 +902,180 @@ pub fn process_instruction(
         .map_err(|_| FreshError::InvalidInstruction)?;
 
     match instruction {
-        MrFreshInstruction::Initialize { mining_difficulty, energy_burst_duration } => {
+        MrFreshInstruction::Initialize {
+            mining_difficulty,
+            energy_burst_duration,
+            emission_mode,
+            proof_required,
+            poop_chance,
+            poop_consolation,
+            config,
+        } => {
             if mining_difficulty < MIN_DIFFICULTY {
                 return Err(FreshError::DifficultyTooLow.into());
             }
-            process_initialize(program_id, accounts, mining_difficulty, energy_burst_duration)
+            if poop_chance != 0 && poop_chance < MIN_POOP_CHANCE {
+                return Err(FreshError::InvalidConfig.into());
+            }
+            let config = config.unwrap_or_default();
+            config.validate(0)?;
+            process_initialize(
+                program_id,
+                accounts,
+                mining_difficulty,
+                energy_burst_duration,
+                emission_mode,
+                proof_required,
+                poop_chance,
+                poop_consolation,
+                &config,
+            )
         }
         MrFreshInstruction::Mine => {
-            process_mining(program_id, accounts)
+            process_mining(program_id, accounts, MineMode::Plain)
         }
         MrFreshInstruction::UpdateDifficulty { new_difficulty } => {
             if new_difficulty < MIN_DIFFICULTY {
                 return Err(FreshError::DifficultyTooLow.into());
             }
-            process_update_difficulty(program_id, accounts, new_difficulty)
+            process_queue_change(program_id, accounts, ParameterChange::Difficulty { new_difficulty })
+        }
+        MrFreshInstruction::CreateMint { name, symbol, uri } => {
+            process_create_mint(program_id, accounts, name, symbol, uri)
+        }
+        MrFreshInstruction::ProposeAuthority { new_authority } => {
+            process_propose_authority(program_id, accounts, new_authority)
+        }
+        MrFreshInstruction::AcceptAuthority => {
+            process_accept_authority(program_id, accounts)
+        }
+        MrFreshInstruction::RenounceAuthority => {
+            process_renounce_authority(program_id, accounts)
+        }
+        MrFreshInstruction::Migrate => {
+            process_migrate(program_id, accounts)
+        }
+        MrFreshInstruction::UpdateConfig { config } => {
+            process_queue_change(program_id, accounts, ParameterChange::Config { config })
+        }
+        MrFreshInstruction::ExecuteQueuedChange => {
+            process_execute_queued_change(program_id, accounts)
+        }
+        MrFreshInstruction::CancelQueuedChange => {
+            process_cancel_queued_change(program_id, accounts)
+        }
+        MrFreshInstruction::Pause => {
+            process_set_paused(program_id, accounts, true)
+        }
+        MrFreshInstruction::Unpause => {
+            process_set_paused(program_id, accounts, false)
+        }
+        MrFreshInstruction::CreateMultisig { threshold, signers } => {
+            process_create_multisig(program_id, accounts, threshold, signers)
+        }
+        MrFreshInstruction::MineWithProof { nonce } => {
+            process_mining(program_id, accounts, MineMode::Proof { nonce })
+        }
+        MrFreshInstruction::CommitMine { hash, nonce } => {
+            process_commit_mine(program_id, accounts, hash, nonce)
+        }
+        MrFreshInstruction::RevealMine { secret } => {
+            process_mining(program_id, accounts, MineMode::Reveal { secret })
+        }
+        MrFreshInstruction::UpdatePoopModel { poop_chance, consolation } => {
+            if poop_chance != 0 && poop_chance < MIN_POOP_CHANCE {
+                return Err(FreshError::InvalidConfig.into());
+            }
+            process_queue_change(program_id, accounts, ParameterChange::PoopModel { poop_chance, consolation })
+        }
+        MrFreshInstruction::UpdateProofRequirement { required } => {
+            process_queue_change(program_id, accounts, ParameterChange::ProofRequirement { required })
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn process_initialize(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     mining_difficulty: u64,
     energy_burst_duration: u64,
+    emission_mode: EmissionMode,
+    proof_required: bool,
+    poop_chance: u64,
+    poop_consolation: bool,
+    config: &FreshConfig,
 ) -> ProgramResult {
     let account_iter = &mut accounts.iter();
     let state_account = next_account_info(account_iter)?;
     let clock_sysvar = next_account_info(account_iter)?;
+    let mint_account = next_account_info(account_iter)?;
+    let token_program = next_account_info(account_iter)?;
+    let authority = next_account_info(account_iter)?;
+    let system_program = next_account_info(account_iter)?;
+    let config_account = next_account_info(account_iter)?;
+    let program_data = next_account_info(account_iter)?;
 
-    if state_account.owner != program_id {
+    // The state lives at a canonical PDA so clients only need the program ID
+    let (state_address, state_bump) = find_state_address(program_id);
+    if state_account.key != &state_address {
+        msg!("State account must be the PDA {}", state_address);
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !state_account.data_is_empty() {
+        if state_account.owner != program_id {
+            return Err(FreshError::InvalidAccountOwner.into());
+        }
+        // Never let a replayed Initialize wipe supply and the halving clock,
+        // whatever layout version the existing account is at
+        if !matches!(load_state(program_id, state_account), Ok(state) if !state.is_initialized) {
+            msg!("Mr. Fresh is already initialized");
+            return Err(FreshError::AlreadyInitialized.into());
+        }
+    }
+    // Otherwise whoever calls Initialize first would own the program forever
+    check_upgrade_authority(program_id, program_data, authority)?;
+    if system_program.key != &solana_system_interface::program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    spl_token_2022::check_spl_token_program_account(token_program.key)?;
+    if mint_account.owner != token_program.key {
+        msg!("Mint must be owned by the selected token program");
         return Err(ProgramError::IncorrectProgramId);
     }
 
+    // The PDA must be the only minter so total_supply tracks the mint's supply
+    let (mint_authority, mint_authority_bump) = find_mint_authority_address(program_id);
+    let mint_data = mint_account.data.borrow();
+    let mint = StateWithExtensions::<Mint>::unpack(&mint_data)?.base;
+    if mint.mint_authority != Some(mint_authority).into() {
+        return Err(FreshError::InvalidMintAuthority.into());
+    }
+    if mint.supply != 0 {
+        msg!("Mint already has a supply of {}", mint.supply);
+        return Err(FreshError::InvalidMint.into());
+    }
+    if mint.decimals != FRESH_DECIMALS {
+        msg!("Mint must have {} decimals, found {}", FRESH_DECIMALS, mint.decimals);
+        return Err(FreshError::InvalidMint.into());
+    }
+
     let clock = Clock::from_account_info(clock_sysvar)?;
+
+    if state_account.data_is_empty() {
+        create_pda_account(
+            authority,
+            state_account,
+            Rent::get()?.minimum_balance(MrFreshState::LEN),
+            MrFreshState::LEN,
+            program_id,
+            system_program,
+            &[STATE_SEED, &[state_bump]],
+        )?;
+    }
     
     let state = MrFreshState {
+        version: STATE_VERSION,
         total_supply: 0,
         mining_difficulty,
         last_mining_timestamp: 0,
@@ -161,120 +1084,1143 @@ fn process_initialize(
         last_energy_burst_slot: 0,
         energy_burst_duration,
         initialization_timestamp: clock.unix_timestamp,
+        mint: *mint_account.key,
+        mint_authority_bump,
+        token_program: *token_program.key,
+        authority: *authority.key,
+        pending_authority: Pubkey::default(),
+        is_initialized: true,
+        next_change_id: 0,
+        paused: false,
+        retarget_window_start: clock.unix_timestamp,
+        poop_chance,
+        poop_consolation,
+        emission_mode,
+        proof_required,
+        challenge: solana_program::hash::hashv(&[state_account.key.as_ref(), &clock.slot.to_le_bytes()]).to_bytes(),
     };
 
-    state.serialize(&mut &mut state_account.data.borrow_mut()[..])?;
+    store_account(state_account, &state)?;
+    create_config_if_missing(program_id, config_account, authority, system_program, config)?;
     msg!("🐱 Mr. Fresh token initialized successfully!");
     Ok(())
 }
 
-fn is_energy_burst_active(clock: &Clock, state: &MrFreshState) -> bool {
-    let slot_since_last = clock.slot.saturating_sub(state.last_energy_burst_slot);
-    let mod_check = clock.slot % 41 == 0;
-    let duration_check = state.last_energy_burst_slot == 0 || slot_since_last >= state.energy_burst_duration;
-    let is_active = duration_check && mod_check;
-    
-    msg!("Debug: Energy burst check details:");
-    msg!("  Current slot: {}", clock.slot);
-    msg!("  Last burst slot: {}", state.last_energy_burst_slot);
-    msg!("  Slots since last: {}", slot_since_last);
-    msg!("  Duration threshold: {}", state.energy_burst_duration);
-    msg!("  Modulo check (slot % 41 == 0): {}", mod_check);
-    msg!("  Duration check result: {}", duration_check);
-    msg!("  Final is_active result: {}", is_active);
-    
-    is_active
+// SlotHashes is too large to deserialize on-chain, so read entries straight
+// from the account: a u64 entry count, then (slot, hash) pairs newest first
+fn slot_hash_entries(data: &[u8]) -> impl Iterator<Item = (u64, [u8; 32])> + '_ {
+    let count = data.get(..8).map_or(0, |count| u64::from_le_bytes(count.try_into().unwrap()));
+    (0..count as usize)
+        .map_while(move |i| data.get(8 + i * 40..8 + (i + 1) * 40))
+        .map(|entry| (u64::from_le_bytes(entry[..8].try_into().unwrap()), entry[8..].try_into().unwrap()))
 }
 
-fn process_mining(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-) -> ProgramResult {
-    let account_iter = &mut accounts.iter();
-    let state_account = next_account_info(account_iter)?;
-    let _miner_account = next_account_info(account_iter)?;
-    let clock_sysvar = next_account_info(account_iter)?;
+// The hash of target_slot, or of the first slot produced after it if it was skipped
+fn slot_hash_at_or_after(data: &[u8], target_slot: u64) -> Option<[u8; 32]> {
+    slot_hash_entries(data)
+        .take_while(|(slot, _)| *slot >= target_slot)
+        .last()
+        .map(|(_, hash)| hash)
+}
 
-    if state_account.owner != program_id {
-        return Err(ProgramError::IncorrectProgramId);
-    }
-    if clock_sysvar.key != &Clock::id() {
-        msg!("Expected Clock sysvar");
+fn check_slot_hashes_sysvar(slot_hashes_sysvar: &AccountInfo) -> ProgramResult {
+    if slot_hashes_sysvar.key != &SlotHashes::id() {
+        msg!("Expected SlotHashes sysvar");
         return Err(ProgramError::InvalidArgument);
     }
+    Ok(())
+}
 
-    let mut state = MrFreshState::try_from_slice(&state_account.data.borrow())?;
-    let clock = Clock::from_account_info(clock_sysvar)?;
-    let current_time = clock.unix_timestamp;
-
-    // Check cooldown period
-    let time_since_last = current_time.saturating_sub(state.last_mining_timestamp);
-    if state.last_mining_timestamp != 0 && time_since_last < MINING_COOLDOWN {
-        let remaining_time = MINING_COOLDOWN - time_since_last;
+fn check_cooldown(miner_record: &MinerAccountView, config: &FreshConfig, current_time: i64) -> ProgramResult {
+    // A commit spends the turn without counting as a mine, so the timestamp,
+    // not mine_count, tells whether this miner has taken a turn yet
+    let last_mining_timestamp = i64::from(miner_record.last_mining_timestamp);
+    let time_since_last = current_time.saturating_sub(last_mining_timestamp);
+    if last_mining_timestamp != MinerAccount::NEVER_MINED && time_since_last < config.mining_cooldown {
+        let remaining_time = config.mining_cooldown - time_since_last;
         msg!("😴 Shhh... Mr. Fresh is taking a proper cat nap!");
         msg!("He needs {:.1} more minutes of sleep!", remaining_time as f64 / 60.0);
         return Err(FreshError::CooldownActive.into());
     }
+    Ok(())
+}
 
-    let slot = clock.slot;
-    if slot != 0 && slot % 10 == 0 && slot < 1000 {
-        msg!("🙀 Oh no! Mr. Fresh found 💩 in the food! Mining failed!");
-        return Err(FreshError::PoopDiscovered.into());
-    }
-
-    // Calculate base reward with halving
-    let mut reward = calculate_mining_reward(&state, current_time)?;
-
-    // Apply bonus mechanisms
-    if is_energy_burst_active(&clock, &state) {
-        msg!("⚡ Mr. Fresh is full of energy! Bonus rewards active!");
-        let pre_bonus_reward = reward;
-        reward = reward.saturating_mul(ENERGY_BURST_BONUS).saturating_div(100);
-        msg!("  Pre-bonus reward: {}", pre_bonus_reward);
-        msg!("  After energy burst bonus: {}", reward);
-        state.last_energy_burst_slot = slot;
-    }
-
-    if slot % LUCKY_PURR_CHANCE == 0 {
-        msg!("😺 *purrrrrr* Mr. Fresh is extra happy! Lucky bonus!");
-        let pre_purr_reward = reward;
-        reward = reward.saturating_mul(LUCKY_PURR_BONUS).saturating_div(100);
-        msg!("  Pre-purr reward: {}", pre_purr_reward);
-        msg!("  After lucky purr bonus: {}", reward);
-    }
-
-    // Ensure reward wouldn't exceed max supply
-    if state.total_supply.saturating_add(reward) > MAX_SUPPLY {
-        reward = MAX_SUPPLY.saturating_sub(state.total_supply);
+fn check_proof(challenge: &[u8; 32], miner: &Pubkey, difficulty: u64, nonce: u64) -> ProgramResult {
+    let hash = proof_hash(challenge, miner, nonce);
+    if !meets_difficulty(&hash, difficulty) {
+        msg!("🙀 Nonce {} does not meet difficulty {}", nonce, difficulty);
+        return Err(FreshError::InvalidProof.into());
     }
+    msg!("🐱 Proof of work accepted");
+    Ok(())
+}
 
-    // Update state
-    state.last_mining_timestamp = current_time;
-    state.total_supply = state.total_supply.saturating_add(reward);
-    state.total_transactions = state.total_transactions.saturating_add(1);
+// No burst lands until energy_burst_duration slots after the last one
+fn is_energy_burst_ready(clock: &Clock, state: &MrFreshStateView) -> bool {
+    let last_energy_burst_slot = u64::from(state.last_energy_burst_slot);
+    let energy_burst_duration = u64::from(state.energy_burst_duration);
+    let slot_since_last = clock.slot.saturating_sub(last_energy_burst_slot);
+    last_energy_burst_slot == 0 || slot_since_last >= energy_burst_duration
+}
 
-    state.serialize(&mut &mut state_account.data.borrow_mut()[..])?;
-    msg!("🐱 Mining successful! Earned {} FRESH tokens!", reward);
-    Ok(())
+fn is_energy_burst_active(clock: &Clock, state: &MrFreshStateView, config: &FreshConfig, roll: u64) -> bool {
+    is_energy_burst_ready(clock, state) && roll % config.energy_burst_interval == 0
 }
 
-fn process_update_difficulty(
+fn process_mining(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    new_difficulty: u64,
+    mode: MineMode,
 ) -> ProgramResult {
     let account_iter = &mut accounts.iter();
     let state_account = next_account_info(account_iter)?;
+    let miner_account = next_account_info(account_iter)?;
+    let clock_sysvar = next_account_info(account_iter)?;
+    let mint_account = next_account_info(account_iter)?;
+    let miner_token_account = next_account_info(account_iter)?;
+    let mint_authority = next_account_info(account_iter)?;
+    let token_program = next_account_info(account_iter)?;
+    let miner_record_account = next_account_info(account_iter)?;
+    let system_program = next_account_info(account_iter)?;
+    let config_account = next_account_info(account_iter)?;
+    // Only a reveal reads SlotHashes
+    let (slot_hashes_sysvar, commitment_account) = match mode {
+        MineMode::Reveal { .. } => (Some(next_account_info(account_iter)?), Some(next_account_info(account_iter)?)),
+        _ => (None, None),
+    };
 
     if state_account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+    if clock_sysvar.key != &Clock::id() {
+        msg!("Expected Clock sysvar");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if let Some(slot_hashes_sysvar) = slot_hashes_sysvar {
+        check_slot_hashes_sysvar(slot_hashes_sysvar)?;
+    }
+    if !miner_account.is_signer {
+        msg!("Miner {} did not sign", miner_account.key);
+        return Err(FreshError::MinerMustSign.into());
+    }
+    for account in [state_account, mint_account, miner_token_account, miner_record_account].into_iter().chain(commitment_account) {
+        if !account.is_writable {
+            msg!("Account {} must be writable", account.key);
+            return Err(FreshError::AccountNotWritable.into());
+        }
+    }
+
+    #[cfg(not(feature = "borsh-mine"))]
+    let mut state = load_state_mut(program_id, state_account)?;
+    // The borsh-mine build decodes and re-encodes every account the way Mine
+    // did before the zero-copy views, as the compute unit test's baseline
+    #[cfg(feature = "borsh-mine")]
+    let mut state = MrFreshStateView::from(&load_state(program_id, state_account)?);
+    if bool::from(state.paused) {
+        msg!("🙀 Mr. Fresh is hiding under the bed, mining is paused!");
+        return Err(FreshError::Paused.into());
+    }
+    if matches!(mode, MineMode::Plain) && bool::from(state.proof_required) {
+        msg!("🙀 Mr. Fresh wants proof of work, use MineWithProof or CommitMine");
+        return Err(FreshError::ProofRequired.into());
+    }
+    // Only Initialize and Migrate create a Config, always at the PDA
+    #[cfg(not(feature = "borsh-mine"))]
+    let config = load_config(program_id, config_account)?;
+    #[cfg(feature = "borsh-mine")]
+    let config = load_account::<FreshConfig>(program_id, config_account)?;
+    if mint_account.key != &state.mint {
+        return Err(FreshError::InvalidMint.into());
+    }
+    if token_program.key != &state.token_program {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Rewards may only land in a token account the signing miner controls
+    if miner_token_account.owner != &state.token_program {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+    {
+        let token_data = miner_token_account.data.borrow();
+        let token_account = StateWithExtensions::<TokenAccount>::unpack(&token_data)?.base;
+        if token_account.owner != *miner_account.key || token_account.mint != state.mint {
+            return Err(FreshError::TokenAccountMismatch.into());
+        }
+    }
+    let mint_authority_seeds: &[&[u8]] = &[MINT_AUTHORITY_SEED, &[state.mint_authority_bump]];
+    if mint_authority.key != &Pubkey::create_program_address(mint_authority_seeds, program_id)? {
+        return Err(FreshError::InvalidMintAuthority.into());
+    }
+
+    let miner_record = load_or_create_miner_record(
+        program_id,
+        miner_account,
+        miner_record_account,
+        system_program,
+    )?;
+    #[cfg(not(feature = "borsh-mine"))]
+    let mut miner_record = miner_record;
+    #[cfg(feature = "borsh-mine")]
+    let mut miner_record = {
+        drop(miner_record);
+        MinerAccountView::from(&load_account::<MinerAccount>(program_id, miner_record_account)?)
+    };
+    let clock = Clock::from_account_info(clock_sysvar)?;
+    let current_time = clock.unix_timestamp;
+
+    // Check this miner's cooldown period; a reveal's turn was checked and
+    // spent by its CommitMine
+    let is_reveal = matches!(mode, MineMode::Reveal { .. });
+    if !is_reveal {
+        check_cooldown(&miner_record, &config, current_time)?;
+    }
+
+    if let MineMode::Proof { nonce } = mode {
+        check_proof(&state.challenge, miner_account.key, state.mining_difficulty.into(), nonce)?;
+    }
+
+    // A reveal rolls from the committed secret and the slot hash after the
+    // commit, which the miner could not know when committing
+    let reveal_roll = match (mode, commitment_account, slot_hashes_sysvar) {
+        (MineMode::Reveal { secret }, Some(commitment_account), Some(slot_hashes_sysvar)) => {
+            let commitment = load_account::<MineCommitment>(program_id, commitment_account)?;
+            if commitment.miner != *miner_account.key {
+                msg!("Commitment belongs to {}", commitment.miner);
+                return Err(ProgramError::InvalidSeeds);
+            }
+            if commitment.is_expired(clock.slot) || commitment.mine_count != u64::from(miner_record.mine_count) {
+                msg!("😿 Commitment from slot {} went stale, commit again", commitment.commit_slot);
+                return Err(FreshError::CommitmentExpired.into());
+            }
+            if commitment_hash(&secret, miner_account.key) != commitment.commitment {
+                msg!("🙀 That secret does not match the commitment");
+                return Err(FreshError::InvalidReveal.into());
+            }
+            let target_slot = commitment.target_slot();
+            let Some(target_slot_hash) = slot_hash_at_or_after(&slot_hashes_sysvar.data.borrow(), target_slot) else {
+                msg!("😼 Patience! Reveal after slot {}", target_slot);
+                return Err(FreshError::RevealTooEarly.into());
+            };
+            Some((reveal_seed(&secret, &target_slot_hash), commitment.commit_slot))
+        }
+        _ => None,
+    };
+
+    // A single-transaction mine could check any roll before sending, or
+    // abort a losing one from a wrapping program, so it is paid the rolls'
+    // expected value instead. A poop still spends the turn, so a reveal that
+    // poops succeeds with no reward rather than rolling the nap back.
+    let slot = clock.slot;
+    let poop_chance = u64::from(state.poop_chance);
+    let mut pooped = false;
+    let mut reward = 0;
+    match reveal_roll {
+        Some((roll_seed, roll_counter)) => {
+            pooped = poop_chance > 0
+                && bonus_roll(&roll_seed, miner_account.key, roll_counter, POOP_SALT) % poop_chance == 0;
+            if pooped {
+                msg!("🙀 Oh no! Mr. Fresh found 💩 in the food! No FRESH this time!");
+            } else {
+                // Calculate base reward with halving
+                reward = calculate_mining_reward(&state, &config, current_time)?;
+
+                // Apply bonus mechanisms
+                let burst_roll = bonus_roll(&roll_seed, miner_account.key, roll_counter, ENERGY_BURST_SALT);
+                let purr_roll = bonus_roll(&roll_seed, miner_account.key, roll_counter, LUCKY_PURR_SALT);
+                if is_energy_burst_active(&clock, &state, &config, burst_roll) {
+                    msg!("⚡ Mr. Fresh is full of energy! Bonus rewards active!");
+                    reward = reward.saturating_mul(config.energy_burst_bonus).saturating_div(100);
+                    state.last_energy_burst_slot = slot.into();
+                }
+
+                if purr_roll % config.lucky_purr_chance == 0 {
+                    msg!("😺 *purrrrrr* Mr. Fresh is extra happy! Lucky bonus!");
+                    reward = reward.saturating_mul(config.lucky_purr_bonus).saturating_div(100);
+                }
+            }
+        }
+        None => {
+            let reward_before_bonuses = calculate_mining_reward(&state, &config, current_time)?;
+            reward = single_transaction_reward(reward_before_bonuses, &config, poop_chance, is_energy_burst_ready(&clock, &state));
+        }
+    }
+
+    // Ensure reward wouldn't exceed max supply
+    let total_supply = u64::from(state.total_supply);
+    if total_supply.saturating_add(reward) > config.max_supply {
+        reward = config.max_supply.saturating_sub(total_supply);
+    }
+
+    // Update state in place
+    state.last_mining_timestamp = current_time.into();
+    state.total_supply = total_supply.saturating_add(reward).into();
+    let total_transactions = u64::from(state.total_transactions).saturating_add(1);
+    state.total_transactions = total_transactions.into();
+    // A miner counts on their first mine, whether or not it paid
+    if u64::from(miner_record.mine_count) == 0 {
+        state.total_miners = u64::from(state.total_miners).saturating_add(1).into();
+    }
+
+    // Retarget at the end of every window of mines
+    if total_transactions % RETARGET_WINDOW == 0 {
+        let old_difficulty = u64::from(state.mining_difficulty);
+        let timespan = current_time.saturating_sub(state.retarget_window_start.into());
+        let new_difficulty = retarget_difficulty(old_difficulty, timespan);
+        msg!("🐱 Retargeting difficulty from {} to {} after {} seconds", old_difficulty, new_difficulty, timespan);
+        state.mining_difficulty = new_difficulty.into();
+        state.retarget_window_start = current_time.into();
+    }
+    let poop_consolation = pooped && bool::from(state.poop_consolation);
+    // A fresh challenge so proofs can never be replayed
+    state.challenge = next_challenge(&state.challenge, slot);
+    #[cfg(not(feature = "borsh-mine"))]
+    drop(state);
+    #[cfg(feature = "borsh-mine")]
+    store_account(state_account, &MrFreshState::from(&state))?;
+    miner_record.last_mining_timestamp = current_time.into();
+    if poop_consolation {
+        // Backdate the nap so only half of it remains
+        miner_record.last_mining_timestamp = current_time.saturating_sub(config.mining_cooldown / 2).into();
+        msg!("😿 Mr. Fresh feels bad about it, your next nap is halved");
+    }
+    miner_record.total_rewards = u64::from(miner_record.total_rewards).saturating_add(reward).into();
+    miner_record.mine_count = u64::from(miner_record.mine_count).saturating_add(1).into();
+    #[cfg(not(feature = "borsh-mine"))]
+    drop(miner_record);
+    #[cfg(feature = "borsh-mine")]
+    store_account(miner_record_account, &MinerAccount::from(&miner_record))?;
+
+    // Mint the reward to the miner, signed by the mint authority PDA
+    if reward > 0 {
+        let mint_to_ix = spl_token_2022::instruction::mint_to(
+            token_program.key,
+            mint_account.key,
+            miner_token_account.key,
+            mint_authority.key,
+            &[],
+            reward,
+        )?;
+        invoke_signed(
+            &mint_to_ix,
+            &[
+                mint_account.clone(),
+                miner_token_account.clone(),
+                mint_authority.clone(),
+                token_program.clone(),
+            ],
+            &[mint_authority_seeds],
+        )?;
+    }
+
+    if let Some(commitment_account) = commitment_account {
+        close_program_account(commitment_account, miner_account)?;
+    }
+    msg!("🐱 Mining successful! Earned {} FRESH tokens!", reward);
+    Ok(())
+}
+
+fn process_commit_mine(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    hash: [u8; 32],
+    nonce: u64,
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_iter)?;
+    let miner_account = next_account_info(account_iter)?;
+    let clock_sysvar = next_account_info(account_iter)?;
+    let miner_record_account = next_account_info(account_iter)?;
+    let commitment_account = next_account_info(account_iter)?;
+    let system_program = next_account_info(account_iter)?;
+    let config_account = next_account_info(account_iter)?;
+
+    if clock_sysvar.key != &Clock::id() {
+        msg!("Expected Clock sysvar");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if !miner_account.is_signer {
+        msg!("Miner {} did not sign", miner_account.key);
+        return Err(FreshError::MinerMustSign.into());
+    }
+    for account in [miner_record_account, commitment_account] {
+        if !account.is_writable {
+            msg!("Account {} must be writable", account.key);
+            return Err(FreshError::AccountNotWritable.into());
+        }
+    }
+
+    let state = load_state(program_id, state_account)?;
+    if state.paused {
+        msg!("🙀 Mr. Fresh is hiding under the bed, mining is paused!");
+        return Err(FreshError::Paused.into());
+    }
+    let config = load_config(program_id, config_account)?;
+    let (commitment_address, bump) = find_commitment_address(program_id, miner_account.key);
+    if commitment_account.key != &commitment_address {
+        msg!("Commitment account must be the PDA {}", commitment_address);
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let mut miner_record = load_or_create_miner_record(
+        program_id,
+        miner_account,
+        miner_record_account,
+        system_program,
+    )?;
+    let clock = Clock::from_account_info(clock_sysvar)?;
+    // An open commitment is what the miner has to act on, so it is reported
+    // ahead of the nap its commit started
+    let previous = if commitment_account.data_is_empty() {
+        None
+    } else {
+        Some(load_account::<MineCommitment>(program_id, commitment_account)?)
+    };
+    if let Some(previous) = &previous {
+        if !previous.is_expired(clock.slot) && previous.mine_count == u64::from(miner_record.mine_count) {
+            msg!("🙀 Reveal the commitment from slot {} first!", previous.commit_slot);
+            return Err(FreshError::CommitmentPending.into());
+        }
+    }
+    check_cooldown(&miner_record, &config, clock.unix_timestamp)?;
+    // The proof goes with the commit, since the reveal only pays it out
+    if state.proof_required {
+        check_proof(&state.challenge, miner_account.key, state.mining_difficulty, nonce)?;
+    }
+
+    if let Some(previous) = previous {
+        msg!("😿 Commitment from slot {} was never revealed, that mine is forfeited", previous.commit_slot);
+    } else {
+        // The miner pays rent for their commitment and gets it back on reveal
+        create_pda_account(
+            miner_account,
+            commitment_account,
+            Rent::get()?.minimum_balance(MineCommitment::LEN),
+            MineCommitment::LEN,
+            program_id,
+            system_program,
+            &[COMMITMENT_SEED, miner_account.key.as_ref(), &[bump]],
+        )?;
+    }
+
+    // Committing spends this turn, so the nap starts now whether or not a
+    // reveal follows
+    miner_record.last_mining_timestamp = clock.unix_timestamp.into();
+    let commitment = MineCommitment {
+        miner: *miner_account.key,
+        commitment: hash,
+        commit_slot: clock.slot,
+        mine_count: miner_record.mine_count.into(),
+    };
+    store_account(commitment_account, &commitment)?;
+
+    msg!("🐱 Mr. Fresh is eyeing the food bowl, reveal after slot {}", commitment.target_slot());
+    msg!("The commitment expires after slot {}", commitment.commit_slot.saturating_add(COMMIT_EXPIRY_SLOTS));
+    Ok(())
+}
+
+fn load_or_create_miner_record<'a, 'info>(
+    program_id: &Pubkey,
+    miner_account: &AccountInfo<'info>,
+    miner_record_account: &'a AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<RefMut<'a, MinerAccountView>, ProgramError> {
+    let (miner_record_address, bump) = find_miner_address(program_id, miner_account.key);
+    if miner_record_account.key != &miner_record_address {
+        msg!("Miner account does not match the PDA for {}", miner_account.key);
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    if system_program.key != &solana_system_interface::program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if miner_record_account.data_is_empty() {
+        // First mine for this miner: the miner pays rent for their own record
+        create_pda_account(
+            miner_account,
+            miner_record_account,
+            Rent::get()?.minimum_balance(MinerAccount::LEN),
+            MinerAccount::LEN,
+            program_id,
+            system_program,
+            &[MINER_SEED, miner_account.key.as_ref(), &[bump]],
+        )?;
+        store_account(miner_record_account, &MinerAccount {
+            miner: *miner_account.key,
+            last_mining_timestamp: MinerAccount::NEVER_MINED,
+            total_rewards: 0,
+            mine_count: 0,
+            bump,
+        })?;
+        msg!("🐱 Welcome, new miner {}!", miner_account.key);
+    }
+
+    load_view_mut::<MinerAccount, MinerAccountView>(program_id, miner_record_account)
+}
+
+/// Layout version of raw state data.
+fn state_version(data: &[u8]) -> Option<u8> {
+    if data.starts_with(&MrFreshState::DISCRIMINATOR) {
+        data.get(DISCRIMINATOR_LEN).copied()
+    } else {
+        None
+    }
+}
+
+/// Reads the state, refusing accounts still on an older layout.
+fn load_state(program_id: &Pubkey, state_account: &AccountInfo) -> Result<MrFreshState, ProgramError> {
+    if state_account.owner == program_id {
+        match state_version(&state_account.data.borrow()) {
+            Some(STATE_VERSION) | None => {}
+            Some(_) => {
+                msg!("State account is not at version {}", STATE_VERSION);
+                return Err(FreshError::StateNeedsMigration.into());
+            }
+        }
+    }
+    load_account(program_id, state_account)
+}
+
+/// Zero-copy counterpart of `load_state`; the view writes straight through to
+/// the account data.
+#[cfg_attr(feature = "borsh-mine", allow(dead_code))]
+fn load_state_mut<'a>(
+    program_id: &Pubkey,
+    state_account: &'a AccountInfo,
+) -> Result<RefMut<'a, MrFreshStateView>, ProgramError> {
+    if state_account.owner == program_id {
+        match state_version(&state_account.data.borrow()) {
+            Some(STATE_VERSION) | None => {}
+            Some(_) => {
+                msg!("State account is not at version {}", STATE_VERSION);
+                return Err(FreshError::StateNeedsMigration.into());
+            }
+        }
+    }
+    load_view_mut::<MrFreshState, MrFreshStateView>(program_id, state_account)
+}
+
+/// The config as the Mine hot path reads it, copied out of the zero-copy view.
+fn load_config(program_id: &Pubkey, config_account: &AccountInfo) -> Result<FreshConfig, ProgramError> {
+    load_view::<FreshConfig, FreshConfigView>(program_id, config_account).map(|view| FreshConfig::from(&*view))
+}
+
+/// Admin instructions must be signed by the authority recorded in state.
+fn assert_authority(
+    program_id: &Pubkey,
+    state: &MrFreshState,
+    authority: &AccountInfo,
+    signers: &[AccountInfo],
+) -> ProgramResult {
+    if state.authority == Pubkey::default() {
+        msg!("Admin parameters are frozen");
+        return Err(FreshError::AuthorityRenounced.into());
+    }
+    assert_signed_by(program_id, &state.authority, authority, signers).inspect_err(|_| {
+        msg!("{} is not the authority", authority.key);
+    })
+}
+
+/// Checks `authority` is `expected` and has signed: either directly, or as a
+/// Multisig whose threshold of distinct signers appear in `signers`.
+fn assert_signed_by(
+    program_id: &Pubkey,
+    expected: &Pubkey,
+    authority: &AccountInfo,
+    signers: &[AccountInfo],
+) -> ProgramResult {
+    if authority.key != expected {
+        return Err(FreshError::Unauthorized.into());
+    }
+    // Checked first so the keypair that created a multisig cannot act alone
+    if authority.owner != program_id {
+        return if authority.is_signer { Ok(()) } else { Err(FreshError::Unauthorized.into()) };
+    }
+
+    let multisig = load_account::<Multisig>(program_id, authority)?;
+    let mut approvals: Vec<&Pubkey> = Vec::with_capacity(multisig.signer_count as usize);
+    for signer in signers {
+        if signer.is_signer && multisig.signers().contains(signer.key) && !approvals.contains(&signer.key) {
+            approvals.push(signer.key);
+        }
+    }
+    if approvals.len() < multisig.threshold as usize {
+        msg!("Multisig {} has {} of {} required signatures", authority.key, approvals.len(), multisig.threshold);
+        return Err(FreshError::Unauthorized.into());
+    }
+    Ok(())
+}
+
+fn process_queue_change(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    change: ParameterChange,
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_iter)?;
+    let authority = next_account_info(account_iter)?;
+    let queued_change_account = next_account_info(account_iter)?;
+    let payer = next_account_info(account_iter)?;
+    let clock_sysvar = next_account_info(account_iter)?;
+    let system_program = next_account_info(account_iter)?;
+
+    if state_account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+    if clock_sysvar.key != &Clock::id() {
+        msg!("Expected Clock sysvar");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if system_program.key != &solana_system_interface::program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut state = load_state(program_id, state_account)?;
+    assert_authority(program_id, &state, authority, account_iter.as_slice())?;
+    // Catch bad values now rather than after the timelock
+    if let ParameterChange::Config { config } = &change {
+        config.validate(state.total_supply)?;
+    }
+
+    let id = state.next_change_id;
+    let (queued_change_address, bump) = find_queued_change_address(program_id, id);
+    if queued_change_account.key != &queued_change_address {
+        msg!("Queued change {} must be the PDA {}", id, queued_change_address);
+        return Err(ProgramError::InvalidSeeds);
+    }
+    create_pda_account(
+        payer,
+        queued_change_account,
+        Rent::get()?.minimum_balance(QueuedChange::LEN),
+        QueuedChange::LEN,
+        program_id,
+        system_program,
+        &[QUEUED_CHANGE_SEED, &id.to_le_bytes(), &[bump]],
+    )?;
+
+    let clock = Clock::from_account_info(clock_sysvar)?;
+    let queued_change = QueuedChange {
+        id,
+        effective_at: clock.unix_timestamp.saturating_add(TIMELOCK_DELAY),
+        rent_payer: *payer.key,
+        change,
+    };
+    store_account(queued_change_account, &queued_change)?;
+    state.next_change_id = id.saturating_add(1);
+    store_account(state_account, &state)?;
+
+    msg!("🐱 Change {} queued: {:?}", id, queued_change.change);
+    msg!("It can be executed after {}", queued_change.effective_at);
+    Ok(())
+}
+
+fn process_create_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    name: String,
+    symbol: String,
+    uri: String,
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let payer = next_account_info(account_iter)?;
+    let mint_account = next_account_info(account_iter)?;
+    let mint_authority = next_account_info(account_iter)?;
+    let token_program = next_account_info(account_iter)?;
+    let system_program = next_account_info(account_iter)?;
+    let program_data = next_account_info(account_iter)?;
+
+    // The metadata can never be changed, so only the deployer may write it
+    check_upgrade_authority(program_id, program_data, payer)?;
+    // Metadata lives in the mint itself, which only Token-2022 supports
+    if token_program.key != &spl_token_2022::id() {
+        msg!("Mint metadata requires the Token-2022 program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if system_program.key != &solana_system_interface::program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let (mint_address, mint_bump) = find_mint_address(program_id);
+    if mint_account.key != &mint_address {
+        return Err(FreshError::InvalidMint.into());
+    }
+    if !mint_account.data_is_empty() || mint_account.owner != &solana_system_interface::program::ID {
+        return Err(FreshError::MintAlreadyCreated.into());
+    }
+    let (mint_authority_address, mint_authority_bump) = find_mint_authority_address(program_id);
+    if mint_authority.key != &mint_authority_address {
+        return Err(FreshError::InvalidMintAuthority.into());
+    }
+
+    // Fund the metadata up front; the token program reallocs the mint when writing it
+    let metadata = TokenMetadata {
+        update_authority: Some(mint_authority_address).try_into()?,
+        mint: mint_address,
+        name: name.clone(),
+        symbol: symbol.clone(),
+        uri: uri.clone(),
+        additional_metadata: vec![],
+    };
+    let mint_len = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::MetadataPointer])?;
+    let lamports = Rent::get()?.minimum_balance(mint_len.saturating_add(metadata.tlv_size_of()?));
+
+    create_pda_account(
+        payer,
+        mint_account,
+        lamports,
+        mint_len,
+        token_program.key,
+        system_program,
+        &[MINT_SEED, &[mint_bump]],
+    )?;
+
+    invoke(
+        &metadata_pointer::instruction::initialize(
+            token_program.key,
+            mint_account.key,
+            Some(mint_authority_address),
+            Some(mint_address),
+        )?,
+        &[mint_account.clone(), token_program.clone()],
+    )?;
+
+    // No freeze authority: nobody can ever freeze FRESH holders
+    invoke(
+        &spl_token_2022::instruction::initialize_mint2(
+            token_program.key,
+            mint_account.key,
+            &mint_authority_address,
+            None,
+            FRESH_DECIMALS,
+        )?,
+        &[mint_account.clone(), token_program.clone()],
+    )?;
+
+    invoke_signed(
+        &spl_token_metadata_interface::instruction::initialize(
+            token_program.key,
+            mint_account.key,
+            &mint_authority_address,
+            mint_account.key,
+            &mint_authority_address,
+            name,
+            symbol,
+            uri,
+        ),
+        &[mint_account.clone(), mint_authority.clone(), token_program.clone()],
+        &[&[MINT_AUTHORITY_SEED, &[mint_authority_bump]]],
+    )?;
+
+    msg!("🐱 FRESH mint created at {}", mint_address);
+    Ok(())
+}
+
+fn process_propose_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_iter)?;
+    let authority = next_account_info(account_iter)?;
+
+    if state_account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+
+    let mut state = load_state(program_id, state_account)?;
+    assert_authority(program_id, &state, authority, account_iter.as_slice())?;
+    // Proposing the default pubkey cancels a pending handover
+    state.pending_authority = new_authority;
+    store_account(state_account, &state)?;
+
+    msg!("🐱 Authority handover proposed to: {}", new_authority);
+    Ok(())
+}
+
+fn process_accept_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_iter)?;
+    let new_authority = next_account_info(account_iter)?;
+
+    if state_account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+
+    let mut state = load_state(program_id, state_account)?;
+    if state.pending_authority == Pubkey::default()
+        || assert_signed_by(program_id, &state.pending_authority, new_authority, account_iter.as_slice()).is_err()
+    {
+        msg!("{} is not the pending authority", new_authority.key);
+        return Err(FreshError::Unauthorized.into());
+    }
+    state.authority = state.pending_authority;
+    state.pending_authority = Pubkey::default();
+    store_account(state_account, &state)?;
+
+    msg!("🐱 Authority handed over to: {}", state.authority);
+    Ok(())
+}
+
+fn process_renounce_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_iter)?;
+    let authority = next_account_info(account_iter)?;
+
+    if state_account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+
+    let mut state = load_state(program_id, state_account)?;
+    assert_authority(program_id, &state, authority, account_iter.as_slice())?;
+    // Nobody could Unpause afterwards, so mining would stay stopped for good
+    if state.paused {
+        msg!("🙀 Unpause before renouncing, or Mr. Fresh hides under the bed forever");
+        return Err(FreshError::Paused.into());
+    }
+    state.authority = Pubkey::default();
+    state.pending_authority = Pubkey::default();
+    store_account(state_account, &state)?;
+
+    msg!("🐱 Authority renounced, admin parameters are now frozen");
+    Ok(())
+}
+
+/// Pausing skips the timelock, it has to work in an emergency.
+fn process_set_paused(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_iter)?;
+    let authority = next_account_info(account_iter)?;
+
+    if state_account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+
+    let mut state = load_state(program_id, state_account)?;
+    assert_authority(program_id, &state, authority, account_iter.as_slice())?;
+    state.paused = paused;
+    store_account(state_account, &state)?;
+
+    if paused {
+        msg!("🐱 Mining paused by {}", authority.key);
+    } else {
+        msg!("🐱 Mining resumed by {}", authority.key);
+    }
+    Ok(())
+}
+
+fn process_create_multisig(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    threshold: u8,
+    signers: Vec<Pubkey>,
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let multisig_account = next_account_info(account_iter)?;
+    let payer = next_account_info(account_iter)?;
+    let system_program = next_account_info(account_iter)?;
+
+    if system_program.key != &solana_system_interface::program::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if threshold == 0 || signers.len() > MAX_MULTISIG_SIGNERS || (threshold as usize) > signers.len() {
+        msg!("Need 1 <= threshold <= signers <= {}", MAX_MULTISIG_SIGNERS);
+        return Err(FreshError::InvalidMultisig.into());
+    }
+    for (i, signer) in signers.iter().enumerate() {
+        if signers[..i].contains(signer) {
+            msg!("Signer {} is listed twice", signer);
+            return Err(FreshError::InvalidMultisig.into());
+        }
+    }
+
+    // A fresh keypair account, so it must sign its own creation
+    invoke(
+        &solana_system_interface::instruction::create_account(
+            payer.key,
+            multisig_account.key,
+            Rent::get()?.minimum_balance(Multisig::LEN),
+            Multisig::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), multisig_account.clone(), system_program.clone()],
+    )?;
+
+    let mut multisig = Multisig {
+        threshold,
+        signer_count: signers.len() as u8,
+        signers: [Pubkey::default(); MAX_MULTISIG_SIGNERS],
+    };
+    multisig.signers[..signers.len()].copy_from_slice(&signers);
+    store_account(multisig_account, &multisig)?;
+
+    msg!("🐱 {}-of-{} multisig created at {}", threshold, signers.len(), multisig_account.key);
+    Ok(())
+}
+
+/// Upgrades the state PDA to the current layout. Deployments of the original
+/// program kept a 64-byte state at a keypair address, with no mint or
+/// authority to carry over, so they need a fresh deploy rather than Migrate.
+fn process_migrate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_iter)?;
+    let payer = next_account_info(account_iter)?;
+    let system_program = next_account_info(account_iter)?;
+    let config_account = next_account_info(account_iter)?;
+
+    if state_account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+    if state_account.key != &find_state_address(program_id).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if system_program.key != &solana_system_interface::program::ID {
         return Err(ProgramError::IncorrectProgramId);
     }
 
-    let mut state = MrFreshState::try_from_slice(&state_account.data.borrow())?;
-    msg!("Debug: Updating difficulty from {} to {}", state.mining_difficulty, new_difficulty);
-    state.mining_difficulty = new_difficulty;
-    state.serialize(&mut &mut state_account.data.borrow_mut()[..])?;
+    // There is only one layout so far. A later STATE_VERSION bump adds its
+    // upgrade here, paid for by `payer`.
+    if state_version(&state_account.data.borrow()) != Some(STATE_VERSION) {
+        msg!("Unrecognized state layout of {} bytes", state_account.data_len());
+        return Err(ProgramError::InvalidAccountData);
+    }
+    msg!("🐱 State is already at version {}", STATE_VERSION);
+
+    // Deployments from before the Config account get the old constants
+    create_config_if_missing(program_id, config_account, payer, system_program, &FreshConfig::default())
+}
+
+/// Creates the config PDA with `config` unless it already exists.
+fn create_config_if_missing<'a>(
+    program_id: &Pubkey,
+    config_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    config: &FreshConfig,
+) -> ProgramResult {
+    let (config_address, config_bump) = find_config_address(program_id);
+    if config_account.key != &config_address {
+        msg!("Config account must be the PDA {}", config_address);
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !config_account.data_is_empty() {
+        return Ok(());
+    }
+
+    create_pda_account(
+        payer,
+        config_account,
+        Rent::get()?.minimum_balance(FreshConfig::LEN),
+        FreshConfig::LEN,
+        program_id,
+        system_program,
+        &[CONFIG_SEED, &[config_bump]],
+    )?;
+    store_account(config_account, config)?;
+    msg!("🐱 Config created");
+    Ok(())
+}
+
+fn process_execute_queued_change(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_iter)?;
+    let authority = next_account_info(account_iter)?;
+    let queued_change_account = next_account_info(account_iter)?;
+    let rent_receiver = next_account_info(account_iter)?;
+    let clock_sysvar = next_account_info(account_iter)?;
+
+    if state_account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+    if clock_sysvar.key != &Clock::id() {
+        msg!("Expected Clock sysvar");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let mut state = load_state(program_id, state_account)?;
+    let queued_change = load_account::<QueuedChange>(program_id, queued_change_account)?;
+    // Only a Config change writes the config account, passed before any
+    // multisig signers
+    let config_account = match queued_change.change {
+        ParameterChange::Config { .. } => {
+            let config_account = next_account_info(account_iter)?;
+            let (config_address, _) = find_config_address(program_id);
+            if config_account.key != &config_address {
+                msg!("Config account must be the PDA {}", config_address);
+                return Err(ProgramError::InvalidSeeds);
+            }
+            Some(config_account)
+        }
+        _ => None,
+    };
+    assert_authority(program_id, &state, authority, account_iter.as_slice())?;
+    let clock = Clock::from_account_info(clock_sysvar)?;
+    if clock.unix_timestamp < queued_change.effective_at {
+        msg!("😴 Change {} unlocks in {} seconds", queued_change.id, queued_change.effective_at - clock.unix_timestamp);
+        return Err(FreshError::TimelockActive.into());
+    }
+
+    match &queued_change.change {
+        ParameterChange::Difficulty { new_difficulty } => {
+            state.mining_difficulty = *new_difficulty;
+            store_account(state_account, &state)?;
+        }
+        ParameterChange::Config { config } => {
+            // Supply may have grown while the change was queued
+            config.validate(state.total_supply)?;
+            let config_account = config_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            // Checks the owner before overwriting
+            load_account::<FreshConfig>(program_id, config_account)?;
+            store_account(config_account, config)?;
+        }
+        ParameterChange::PoopModel { poop_chance, consolation } => {
+            state.poop_chance = *poop_chance;
+            state.poop_consolation = *consolation;
+            store_account(state_account, &state)?;
+        }
+        ParameterChange::ProofRequirement { required } => {
+            msg!("🐱 Proof of work is now {}", if *required { "required" } else { "optional" });
+            state.proof_required = *required;
+            store_account(state_account, &state)?;
+        }
+    }
+    close_queued_change(&queued_change, queued_change_account, rent_receiver)?;
+
+    msg!("🐱 Change {} executed!", queued_change.id);
+    Ok(())
+}
+
+fn process_cancel_queued_change(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_iter = &mut accounts.iter();
+    let state_account = next_account_info(account_iter)?;
+    let authority = next_account_info(account_iter)?;
+    let queued_change_account = next_account_info(account_iter)?;
+    let rent_receiver = next_account_info(account_iter)?;
+
+    if state_account.owner != program_id {
+        return Err(FreshError::InvalidAccountOwner.into());
+    }
+
+    let state = load_state(program_id, state_account)?;
+    // Once the authority is renounced a queued change can never execute, so
+    // anyone may close it and return the rent to whoever queued it
+    if state.authority != Pubkey::default() {
+        assert_authority(program_id, &state, authority, account_iter.as_slice())?;
+    }
+    let queued_change = load_account::<QueuedChange>(program_id, queued_change_account)?;
+    close_queued_change(&queued_change, queued_change_account, rent_receiver)?;
+
+    msg!("🐱 Change {} cancelled", queued_change.id);
+    Ok(())
+}
+
+/// Refunds the rent to whoever queued the change and hands the account back
+/// to the system program.
+fn close_queued_change(
+    queued_change: &QueuedChange,
+    queued_change_account: &AccountInfo,
+    rent_receiver: &AccountInfo,
+) -> ProgramResult {
+    if rent_receiver.key != &queued_change.rent_payer {
+        msg!("Rent must be returned to {}", queued_change.rent_payer);
+        return Err(ProgramError::InvalidArgument);
+    }
+    close_program_account(queued_change_account, rent_receiver)
+}
 
-    msg!("🐱 Mining difficulty updated to: {}", new_difficulty);
+/// Creates a PDA owned by `owner`. Anyone can send lamports to the address
+/// first, which makes create_account fail, so an already funded account is
+/// topped up to `lamports` and then allocated and assigned instead.
+fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    lamports: u64,
+    space: usize,
+    owner: &Pubkey,
+    system_program: &AccountInfo<'a>,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    if account.lamports() == 0 {
+        return invoke_signed(
+            &solana_system_interface::instruction::create_account(payer.key, account.key, lamports, space as u64, owner),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[signer_seeds],
+        );
+    }
+
+    let top_up = lamports.saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &solana_system_interface::instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    invoke_signed(
+        &solana_system_interface::instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &solana_system_interface::instruction::assign(account.key, owner),
+        &[account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Requires `authority` to sign as the program's upgrade authority, read from
+/// its ProgramData account: a u32 tag of 3, the deploy slot, then an
+/// Option<Pubkey>.
+fn check_upgrade_authority(program_id: &Pubkey, program_data: &AccountInfo, authority: &AccountInfo) -> ProgramResult {
+    let program_data_address = get_program_data_address(program_id);
+    if program_data.key != &program_data_address || program_data.owner != &solana_sdk_ids::bpf_loader_upgradeable::ID {
+        msg!("Expected the program's ProgramData account {}", program_data_address);
+        return Err(ProgramError::InvalidArgument);
+    }
+    let data = program_data.try_borrow_data()?;
+    let upgrade_authority = match data.get(..UpgradeableLoaderState::size_of_programdata_metadata()) {
+        Some([3, 0, 0, 0, metadata @ ..]) if metadata[8] == 1 => Pubkey::try_from(&metadata[9..]).ok(),
+        _ => None,
+    };
+    if upgrade_authority != Some(*authority.key) {
+        msg!("Only the program's upgrade authority can do this");
+        return Err(FreshError::Unauthorized.into());
+    }
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+fn close_program_account(account: &AccountInfo, rent_receiver: &AccountInfo) -> ProgramResult {
+    let lamports = account.lamports();
+    **rent_receiver.try_borrow_mut_lamports()? = rent_receiver
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    **account.try_borrow_mut_lamports()? = 0;
+    account.resize(0)?;
+    account.assign(&solana_system_interface::program::ID);
+    Ok(())
+}
@@ -1,26 +1,35 @@
 use super::*;
 use solana_program::{
     instruction::{AccountMeta, Instruction, InstructionError},
-    system_instruction,
     clock::Clock,
-    sysvar::clock::ID as CLOCK_ID,
+    program_pack::Pack,
+    slot_hashes::SlotHashes,
+    sysvar::{clock::ID as CLOCK_ID, slot_hashes::ID as SLOT_HASHES_ID},
 };
 use solana_program_test::*;
 use solana_sdk::{
+    account::Account,
     signature::{Keypair, Signer},
     transaction::Transaction,
     transaction::TransactionError,
     hash::Hash,
 };
-use std::mem;
-use borsh::{BorshSerialize, BorshDeserialize};
+use borsh::BorshSerialize;
+use spl_token::state::Account as TokenAccount;
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+use spl_token_metadata_interface::state::TokenMetadata;
+
+// The poop odds tests opt in to
+const POOP_CHANCE: u64 = 20;
 
 async fn setup_test_context(initial_time: i64, slot: u64) -> (ProgramTestContext, Pubkey) {
     let mut program_test = ProgramTest::default();
     let program_id = Pubkey::new_unique();
     
     program_test.add_program("mr_fresh", program_id, processor!(process_instruction));
-    let context = program_test.start_with_context().await;
+    let mut context = program_test.start_with_context().await;
+    let deployer = context.payer.pubkey();
+    set_upgrade_authority(&mut context, &program_id, &deployer).await;
     
     let clock = Clock {
         slot,
@@ -35,54 +44,204 @@ async fn setup_test_context(initial_time: i64, slot: u64) -> (ProgramTestContext
     (context, program_id)
 }
 
-async fn create_test_state(
+// Stands in for the ProgramData account the upgradeable loader would create,
+// keeping the program bytes if the program really was deployed through it
+async fn set_upgrade_authority(context: &mut ProgramTestContext, program_id: &Pubkey, authority: &Pubkey) {
+    let program_data_address = solana_loader_v3_interface::get_program_data_address(program_id);
+    let mut account = context.banks_client.get_account(program_data_address).await.unwrap().unwrap_or_else(|| Account {
+        lamports: 1_000_000_000,
+        data: vec![0; UpgradeableLoaderState::size_of_programdata_metadata()],
+        owner: solana_sdk_ids::bpf_loader_upgradeable::ID,
+        executable: false,
+        rent_epoch: 0,
+    });
+    account.data[..4].copy_from_slice(&3u32.to_le_bytes());
+    account.data[12] = 1;
+    account.data[13..45].copy_from_slice(authority.as_ref());
+    context.set_account(&program_data_address, &account.into());
+}
+
+async fn create_funded_miner(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: &Hash,
+) -> Result<Keypair, BanksClientError> {
+    // Miners pay rent for their own miner record on first mine
+    let miner = Keypair::new();
+    let transaction = Transaction::new_signed_with_payer(
+        &[solana_system_interface::instruction::transfer(&payer.pubkey(), &miner.pubkey(), 10_000_000)],
+        Some(&payer.pubkey()),
+        &[payer],
+        *recent_blockhash,
+    );
+
+    banks_client.process_transaction(transaction).await?;
+    Ok(miner)
+}
+
+// Anyone can send lamports to a PDA before the program creates it
+async fn prefund_account(context: &mut ProgramTestContext, address: &Pubkey) {
+    // The runtime refuses to leave a fresh account below the rent-exempt minimum
+    let lamports = context.banks_client.get_rent().await.unwrap().minimum_balance(0);
+    let transaction = Transaction::new_signed_with_payer(
+        &[solana_system_interface::instruction::transfer(&context.payer.pubkey(), address, lamports)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+}
+
+async fn create_test_mint(
     banks_client: &mut BanksClient,
     payer: &Keypair,
     recent_blockhash: &Hash,
     program_id: &Pubkey,
-    _initial_time: i64,
+    token_program_id: &Pubkey,
 ) -> Result<Keypair, BanksClientError> {
-    let state_account = Keypair::new();
-    println!("Debug: Creating state account: {}", state_account.pubkey());
-    
+    let mint = Keypair::new();
+    let (mint_authority, _) = find_mint_authority_address(program_id);
+
     let rent = banks_client.get_rent().await?;
-    let account_size = mem::size_of::<MrFreshState>();
-    let lamports = rent.minimum_balance(account_size);
+    let instructions = [
+        solana_system_interface::instruction::create_account(
+            &payer.pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            token_program_id,
+        ),
+        spl_token_2022::instruction::initialize_mint2(
+            token_program_id,
+            &mint.pubkey(),
+            &mint_authority,
+            None,
+            9,
+        ).unwrap(),
+    ];
 
-    let create_account_ix = system_instruction::create_account(
-        &payer.pubkey(),
-        &state_account.pubkey(),
-        lamports,
-        account_size as u64,
-        program_id,
+    let transaction = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&payer.pubkey()),
+        &[payer, &mint],
+        *recent_blockhash,
     );
 
+    banks_client.process_transaction(transaction).await?;
+    Ok(mint)
+}
+
+async fn create_token_account(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: &Hash,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Result<Pubkey, BanksClientError> {
+    let token_account = Keypair::new();
+
+    let rent = banks_client.get_rent().await?;
+    let instructions = [
+        solana_system_interface::instruction::create_account(
+            &payer.pubkey(),
+            &token_account.pubkey(),
+            rent.minimum_balance(TokenAccount::LEN),
+            TokenAccount::LEN as u64,
+            token_program_id,
+        ),
+        spl_token_2022::instruction::initialize_account3(
+            token_program_id,
+            &token_account.pubkey(),
+            mint,
+            owner,
+        ).unwrap(),
+    ];
+
     let transaction = Transaction::new_signed_with_payer(
-        &[create_account_ix],
+        &instructions,
         Some(&payer.pubkey()),
-        &[payer, &state_account],
+        &[payer, &token_account],
         *recent_blockhash,
     );
 
     banks_client.process_transaction(transaction).await?;
+    Ok(token_account.pubkey())
+}
+
+fn create_mint_instruction(program_id: &Pubkey, payer: &Pubkey) -> Instruction {
+    let mut buffer = Vec::new();
+    MrFreshInstruction::CreateMint {
+        name: "Mr. Fresh".to_string(),
+        symbol: "FRESH".to_string(),
+        uri: "https://github.com/PTVincent/Mr-Fresh-Coin".to_string(),
+    }.serialize(&mut buffer).unwrap();
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(find_mint_address(program_id).0, false),
+            AccountMeta::new_readonly(find_mint_authority_address(program_id).0, false),
+            AccountMeta::new_readonly(spl_token_2022::id(), false),
+            AccountMeta::new_readonly(solana_system_interface::program::ID, false),
+            AccountMeta::new_readonly(solana_loader_v3_interface::get_program_data_address(program_id), false),
+        ],
+        data: buffer,
+    }
+}
 
-    let instruction_data = MrFreshInstruction::Initialize {
+// The fixture's Initialize: difficulty 1000, proofs required
+fn initialize_data(emission_mode: EmissionMode, config: Option<FreshConfig>) -> MrFreshInstruction {
+    MrFreshInstruction::Initialize {
         mining_difficulty: 1000,
         energy_burst_duration: 100,
-    };
-    
+        emission_mode,
+        proof_required: true,
+        poop_chance: 0,
+        poop_consolation: false,
+        config,
+    }
+}
+
+fn create_initialize_instruction(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    mint: &Pubkey,
+    token_program_id: &Pubkey,
+    instruction_data: MrFreshInstruction,
+) -> Instruction {
     let mut buffer = Vec::new();
     instruction_data.serialize(&mut buffer).unwrap();
 
-    let instruction = Instruction {
+    Instruction {
         program_id: *program_id,
         accounts: vec![
-            AccountMeta::new(state_account.pubkey(), false),
+            AccountMeta::new(find_state_address(program_id).0, false),
             AccountMeta::new_readonly(CLOCK_ID, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(*token_program_id, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(solana_system_interface::program::ID, false),
+            AccountMeta::new(find_config_address(program_id).0, false),
+            AccountMeta::new_readonly(solana_loader_v3_interface::get_program_data_address(program_id), false),
         ],
         data: buffer,
-    };
+    }
+}
+
+async fn create_test_state(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: &Hash,
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Result<Pubkey, BanksClientError> {
+    let (state_account, _) = find_state_address(program_id);
+    println!("Debug: Initializing state account: {}", state_account);
 
+    let instruction = create_initialize_instruction(program_id, &payer.pubkey(), mint, token_program_id, initialize_data(EmissionMode::Step, None));
     let transaction = Transaction::new_signed_with_payer(
         &[instruction],
         Some(&payer.pubkey()),
@@ -94,11 +253,201 @@ async fn create_test_state(
     Ok(state_account)
 }
 
+// Funded miner, mint, the miner's token account and an initialized state
+// that requires proofs, like a deployment with the SDK and CLI defaults
+async fn setup_mining_fixture(
+    context: &mut ProgramTestContext,
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+) -> (Keypair, Keypair, Pubkey, Pubkey) {
+    setup_mining_fixture_with(context, program_id, token_program_id, EmissionMode::Step, true).await
+}
+
+// The same, but also accepting plain Mine, for tests of the mining mechanics
+async fn setup_plain_mining_fixture(
+    context: &mut ProgramTestContext,
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+) -> (Keypair, Keypair, Pubkey, Pubkey) {
+    setup_mining_fixture_with(context, program_id, token_program_id, EmissionMode::Step, false).await
+}
+
+async fn setup_mining_fixture_with(
+    context: &mut ProgramTestContext,
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    emission_mode: EmissionMode,
+    proof_required: bool,
+) -> (Keypair, Keypair, Pubkey, Pubkey) {
+    let payer = context.payer.insecure_clone();
+    let miner = create_funded_miner(&mut context.banks_client, &payer, &context.last_blockhash)
+        .await
+        .unwrap();
+    let mint = create_test_mint(&mut context.banks_client, &payer, &context.last_blockhash, program_id, token_program_id)
+        .await
+        .unwrap();
+    let miner_token_account = create_token_account(
+        &mut context.banks_client,
+        &payer,
+        &context.last_blockhash,
+        &mint.pubkey(),
+        &miner.pubkey(),
+        token_program_id,
+    ).await.unwrap();
+
+    let mut instruction_data = initialize_data(emission_mode, None);
+    if let MrFreshInstruction::Initialize { proof_required: required, .. } = &mut instruction_data {
+        *required = proof_required;
+    }
+    let instruction = create_initialize_instruction(program_id, &payer.pubkey(), &mint.pubkey(), token_program_id, instruction_data);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    (miner, mint, miner_token_account, find_state_address(program_id).0)
+}
+
+fn create_update_difficulty_instruction(
+    program_id: &Pubkey,
+    state_account: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    change_id: u64,
+    new_difficulty: u64,
+) -> Instruction {
+    let mut buffer = Vec::new();
+    MrFreshInstruction::UpdateDifficulty { new_difficulty }.serialize(&mut buffer).unwrap();
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*state_account, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(find_queued_change_address(program_id, change_id).0, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(CLOCK_ID, false),
+            AccountMeta::new_readonly(solana_system_interface::program::ID, false),
+        ],
+        data: buffer,
+    }
+}
+
+fn create_resolve_change_instruction(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    rent_receiver: &Pubkey,
+    change_id: u64,
+    instruction_data: MrFreshInstruction,
+) -> Instruction {
+    let mut buffer = Vec::new();
+    instruction_data.serialize(&mut buffer).unwrap();
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(find_state_address(program_id).0, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(find_queued_change_address(program_id, change_id).0, false),
+            AccountMeta::new(*rent_receiver, false),
+            AccountMeta::new_readonly(CLOCK_ID, false),
+        ],
+        data: buffer,
+    }
+}
+
+// Executing a Config change also takes the config account
+fn with_config_account(mut instruction: Instruction) -> Instruction {
+    let (config_account, _) = find_config_address(&instruction.program_id);
+    instruction.accounts.push(AccountMeta::new(config_account, false));
+    instruction
+}
+
+fn create_authority_instruction(
+    program_id: &Pubkey,
+    state_account: &Pubkey,
+    signer: &Pubkey,
+    instruction_data: MrFreshInstruction,
+) -> Instruction {
+    let mut buffer = Vec::new();
+    instruction_data.serialize(&mut buffer).unwrap();
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*state_account, false),
+            AccountMeta::new_readonly(*signer, true),
+        ],
+        data: buffer,
+    }
+}
+
+fn create_update_config_instruction(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    change_id: u64,
+    config: FreshConfig,
+) -> Instruction {
+    let mut buffer = Vec::new();
+    MrFreshInstruction::UpdateConfig { config }.serialize(&mut buffer).unwrap();
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(find_state_address(program_id).0, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(find_queued_change_address(program_id, change_id).0, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(CLOCK_ID, false),
+            AccountMeta::new_readonly(solana_system_interface::program::ID, false),
+        ],
+        data: buffer,
+    }
+}
+
+fn create_multisig_instruction(
+    program_id: &Pubkey,
+    multisig: &Pubkey,
+    payer: &Pubkey,
+    threshold: u8,
+    signers: Vec<Pubkey>,
+) -> Instruction {
+    let mut buffer = Vec::new();
+    MrFreshInstruction::CreateMultisig { threshold, signers }.serialize(&mut buffer).unwrap();
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*multisig, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(solana_system_interface::program::ID, false),
+        ],
+        data: buffer,
+    }
+}
+
+// Swaps the authority (always the second account) for a multisig and appends its signers
+fn with_multisig_signers(mut instruction: Instruction, multisig: &Pubkey, signers: &[&Keypair]) -> Instruction {
+    instruction.accounts[1] = AccountMeta::new_readonly(*multisig, false);
+    for signer in signers {
+        instruction.accounts.push(AccountMeta::new_readonly(signer.pubkey(), true));
+    }
+    instruction
+}
+
+fn calculate_base_reward(initial_time: i64, current_time: i64, mining_difficulty: u64) -> u64 {
+    halving_loop_reward(INITIAL_BASE_REWARD, HALVING_INTERVAL, current_time.saturating_sub(initial_time), mining_difficulty)
+}
+
+// What a plain Mine or MineWithProof pays under the default config with poop off
 fn calculate_expected_reward(initial_time: i64, current_time: i64, mining_difficulty: u64) -> u64 {
-    let time_since_start = current_time.saturating_sub(initial_time);
-    let halving_epoch = time_since_start / HALVING_INTERVAL;
+    single_transaction_reward(calculate_base_reward(initial_time, current_time, mining_difficulty), &FreshConfig::default(), 0, true)
+}
+
+// The original halve-once-per-epoch loop, kept as the reference for emission_at
+fn halving_loop_reward(initial_base_reward: u64, halving_interval: i64, time_since_start: i64, mining_difficulty: u64) -> u64 {
+    let halving_epoch = time_since_start / halving_interval;
     
-    let mut current_base_reward = INITIAL_BASE_REWARD;
+    let mut current_base_reward = initial_base_reward;
     for _ in 0..halving_epoch {
         current_base_reward = current_base_reward.saturating_div(2);
     }
@@ -108,11 +457,14 @@ fn calculate_expected_reward(initial_time: i64, current_time: i64, mining_diffic
 
 fn create_mine_instruction(
     program_id: &Pubkey,
-    state_account: &Keypair,
+    state_account: &Pubkey,
     miner: &Keypair,
+    mint: &Pubkey,
+    miner_token_account: &Pubkey,
+    token_program_id: &Pubkey,
 ) -> Instruction {
     println!("Debug: Creating mine instruction");
-    println!("Debug: State account: {}", state_account.pubkey());
+    println!("Debug: State account: {}", state_account);
     println!("Debug: Miner account: {}", miner.pubkey());
     
     let mut buffer = Vec::new();
@@ -122,14 +474,133 @@ fn create_mine_instruction(
     Instruction {
         program_id: *program_id,
         accounts: vec![
-            AccountMeta::new(state_account.pubkey(), false),
+            AccountMeta::new(*state_account, false),
             AccountMeta::new(miner.pubkey(), true),
             AccountMeta::new_readonly(CLOCK_ID, false),
+            AccountMeta::new(*mint, false),
+            AccountMeta::new(*miner_token_account, false),
+            AccountMeta::new_readonly(find_mint_authority_address(program_id).0, false),
+            AccountMeta::new_readonly(*token_program_id, false),
+            AccountMeta::new(find_miner_address(program_id, &miner.pubkey()).0, false),
+            AccountMeta::new_readonly(solana_system_interface::program::ID, false),
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
         ],
         data: buffer,
     }
 }
 
+fn create_mine_with_proof_instruction(
+    program_id: &Pubkey,
+    state_account: &Pubkey,
+    miner: &Keypair,
+    mint: &Pubkey,
+    miner_token_account: &Pubkey,
+    token_program_id: &Pubkey,
+    nonce: u64,
+) -> Instruction {
+    let mut instruction = create_mine_instruction(program_id, state_account, miner, mint, miner_token_account, token_program_id);
+    instruction.data.clear();
+    MrFreshInstruction::MineWithProof { nonce }.serialize(&mut instruction.data).unwrap();
+    instruction
+}
+
+fn create_update_poop_model_instruction(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    change_id: u64,
+    poop_chance: u64,
+    consolation: bool,
+) -> Instruction {
+    let mut instruction = create_update_config_instruction(program_id, authority, payer, change_id, FreshConfig::default());
+    instruction.data.clear();
+    MrFreshInstruction::UpdatePoopModel { poop_chance, consolation }.serialize(&mut instruction.data).unwrap();
+    instruction
+}
+
+fn create_update_proof_requirement_instruction(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    change_id: u64,
+    required: bool,
+) -> Instruction {
+    let mut instruction = create_update_config_instruction(program_id, authority, payer, change_id, FreshConfig::default());
+    instruction.data.clear();
+    MrFreshInstruction::UpdateProofRequirement { required }.serialize(&mut instruction.data).unwrap();
+    instruction
+}
+
+fn create_commit_mine_instruction(program_id: &Pubkey, state_account: &Pubkey, miner: &Pubkey, hash: [u8; 32], nonce: u64) -> Instruction {
+    let mut buffer = Vec::new();
+    MrFreshInstruction::CommitMine { hash, nonce }.serialize(&mut buffer).unwrap();
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*state_account, false),
+            AccountMeta::new(*miner, true),
+            AccountMeta::new_readonly(CLOCK_ID, false),
+            AccountMeta::new(find_miner_address(program_id, miner).0, false),
+            AccountMeta::new(find_commitment_address(program_id, miner).0, false),
+            AccountMeta::new_readonly(solana_system_interface::program::ID, false),
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        ],
+        data: buffer,
+    }
+}
+
+fn create_reveal_mine_instruction(
+    program_id: &Pubkey,
+    state_account: &Pubkey,
+    miner: &Keypair,
+    mint: &Pubkey,
+    miner_token_account: &Pubkey,
+    token_program_id: &Pubkey,
+    secret: [u8; 32],
+) -> Instruction {
+    let mut instruction = create_mine_instruction(program_id, state_account, miner, mint, miner_token_account, token_program_id);
+    instruction.data.clear();
+    MrFreshInstruction::RevealMine { secret }.serialize(&mut instruction.data).unwrap();
+    instruction.accounts.push(AccountMeta::new_readonly(SLOT_HASHES_ID, false));
+    instruction.accounts.push(AccountMeta::new(find_commitment_address(program_id, &miner.pubkey()).0, false));
+    instruction
+}
+
+/// Moves the bank to `slot` with the given clock time and SlotHashes
+/// entries, and takes a fresh blockhash so retried instructions are new
+/// transactions.
+async fn warp_with_slot_hashes(context: &mut ProgramTestContext, slot: u64, unix_timestamp: i64, slot_hashes: &[(u64, Hash)]) {
+    // Jumping several slots at once makes the bank hash every account into an
+    // on-disk cache, which can exhaust file descriptors, so step one at a time
+    let reported_slot = context.banks_client.get_root_slot().await.unwrap();
+    for next_slot in reported_slot + 1..=slot {
+        match context.warp_to_slot(next_slot) {
+            // The working bank may already have ticked past this slot
+            Ok(()) | Err(ProgramTestError::InvalidWarpSlot) => {}
+        }
+    }
+    context.set_sysvar(&Clock {
+        slot,
+        epoch_start_timestamp: unix_timestamp,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp,
+    });
+    context.set_sysvar(&SlotHashes::new(slot_hashes));
+    context.last_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+}
+
+fn find_nonce(challenge: &[u8; 32], miner: &Pubkey, difficulty: u64, valid: bool) -> u64 {
+    (0..).find(|nonce| meets_difficulty(&proof_hash(challenge, miner, *nonce), difficulty) == valid).unwrap()
+}
+
+fn find_slot_hash(predicate: impl Fn(&[u8; 32]) -> bool) -> Hash {
+    (0u64..)
+        .map(|seed| solana_program::hash::hash(&seed.to_le_bytes()))
+        .find(|hash| predicate(&hash.to_bytes()))
+        .unwrap()
+}
+
 async fn process_mining_transaction(
     banks_client: &mut BanksClient,
     instruction: Instruction,
@@ -153,12 +624,12 @@ async fn process_mining_transaction(
 
 async fn verify_mining_result(
     banks_client: &mut BanksClient,
-    state_account: &Keypair,
+    state_account: &Pubkey,
     expected_reward: Option<u64>,
 ) -> Result<MrFreshState, BanksClientError> {
     println!("Debug: Verifying mining result");
-    let account = banks_client.get_account(state_account.pubkey()).await?.unwrap();
-    let state = MrFreshState::try_from_slice(&account.data).unwrap();
+    let account = banks_client.get_account(*state_account).await?.unwrap();
+    let state = unpack_account::<MrFreshState>(&account.data).unwrap();
     
     println!("Debug: Current state:");
     println!("Debug: Total supply: {}", state.total_supply);
@@ -178,31 +649,154 @@ async fn verify_mining_result(
     Ok(state)
 }
 
+fn assert_fresh_error(result: Result<(), BanksClientError>, expected: FreshError) {
+    let expected_code = expected as u32;
+    assert!(
+        matches!(
+            result,
+            Err(BanksClientError::TransactionError(
+                TransactionError::InstructionError(_, InstructionError::Custom(err))
+            )) if err == expected_code
+        ),
+        "Expected error code {}, got: {:?}",
+        expected_code,
+        result
+    );
+}
+
+async fn get_miner_record(
+    banks_client: &mut BanksClient,
+    program_id: &Pubkey,
+    miner: &Pubkey,
+) -> MinerAccount {
+    let (miner_record, _) = find_miner_address(program_id, miner);
+    let account = banks_client.get_account(miner_record).await.unwrap().unwrap();
+    unpack_account::<MinerAccount>(&account.data).unwrap()
+}
+
+async fn get_token_balance(banks_client: &mut BanksClient, token_account: &Pubkey) -> u64 {
+    let account = banks_client.get_account(*token_account).await.unwrap().unwrap();
+    TokenAccount::unpack(&account.data).unwrap().amount
+}
+
 #[tokio::test]
 async fn test_initialization() {
     println!("\n=== Running Initialization Test ===");
     let initial_time = 0;
     let (mut context, program_id) = setup_test_context(initial_time, 0).await;
-    
-    let result = create_test_state(
+    let (_, mint, _, state_account) = setup_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+
+    let state = verify_mining_result(&mut context.banks_client, &state_account, Some(0))
+        .await
+        .unwrap();
+    assert_eq!(state.total_supply, 0);
+    assert_eq!(state.total_transactions, 0);
+    assert_eq!(state.last_mining_timestamp, 0);
+    assert_eq!(state.initialization_timestamp, initial_time);
+    assert_eq!(state.mint, mint.pubkey());
+    assert_eq!(state.token_program, spl_token::id());
+    assert_eq!(state.authority, context.payer.pubkey());
+    assert!(state.is_initialized);
+    assert!(state.proof_required);
+    assert_eq!(state_account, find_state_address(&program_id).0);
+
+    let config_account = context.banks_client.get_account(find_config_address(&program_id).0).await.unwrap().unwrap();
+    let config = unpack_account::<FreshConfig>(&config_account.data).unwrap();
+    assert_eq!(config, FreshConfig::default());
+    assert_eq!(config.mining_cooldown, MINING_COOLDOWN);
+}
+
+#[tokio::test]
+async fn test_initialize_requires_upgrade_authority() {
+    println!("\n=== Running Initialize Authority Test ===");
+    let (mut context, program_id) = setup_test_context(0, 1).await;
+    let payer = context.payer.insecure_clone();
+    let impostor = create_funded_miner(&mut context.banks_client, &payer, &context.last_blockhash)
+        .await
+        .unwrap();
+    let mint = create_test_mint(&mut context.banks_client, &payer, &context.last_blockhash, &program_id, &spl_token::id())
+        .await
+        .unwrap();
+
+    // Lamports sent to the state address ahead of time must not block Initialize
+    let (state_account, _) = find_state_address(&program_id);
+    prefund_account(&mut context, &state_account).await;
+
+    // Racing the deployer to Initialize must not hand over the program
+    let instruction = create_initialize_instruction(&program_id, &impostor.pubkey(), &mint.pubkey(), &spl_token::id(), initialize_data(EmissionMode::Step, None));
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer, &impostor], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    create_test_state(
         &mut context.banks_client,
-        &context.payer,
+        &payer,
         &context.last_blockhash,
         &program_id,
-        initial_time,
-    ).await;
-    
-    assert!(result.is_ok(), "Failed to initialize state");
-    
-    if let Ok(state_account) = result {
-        let state = verify_mining_result(&mut context.banks_client, &state_account, Some(0))
-            .await
-            .unwrap();
-        assert_eq!(state.total_supply, 0);
-        assert_eq!(state.total_transactions, 0);
-        assert_eq!(state.last_mining_timestamp, 0);
-        assert_eq!(state.initialization_timestamp, initial_time);
+        &mint.pubkey(),
+        &spl_token::id(),
+    ).await.unwrap();
+    let state = verify_mining_result(&mut context.banks_client, &state_account, Some(0))
+        .await
+        .unwrap();
+    assert_eq!(state.authority, payer.pubkey());
+}
+
+#[tokio::test]
+async fn test_initialize_with_config() {
+    println!("\n=== Running Initialize With Config Test ===");
+    let (mut context, program_id) = setup_test_context(0, 1).await;
+    let payer = context.payer.insecure_clone();
+    let mint = create_test_mint(&mut context.banks_client, &payer, &context.last_blockhash, &program_id, &spl_token::id())
+        .await
+        .unwrap();
+
+    // The deployer's economics go through the same bounds as UpdateConfig
+    let bad_config = FreshConfig { energy_burst_interval: 0, ..FreshConfig::default() };
+    let instruction = create_initialize_instruction(&program_id, &payer.pubkey(), &mint.pubkey(), &spl_token::id(), initialize_data(EmissionMode::Step, Some(bad_config)));
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::InvalidConfig);
+
+    // So do the poop odds, which could otherwise make every mine fail
+    let mut bad_poop = initialize_data(EmissionMode::Step, None);
+    if let MrFreshInstruction::Initialize { poop_chance, .. } = &mut bad_poop {
+        *poop_chance = 1;
     }
+    let instruction = create_initialize_instruction(&program_id, &payer.pubkey(), &mint.pubkey(), &spl_token::id(), bad_poop);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::InvalidConfig);
+
+    // Lamports sent to the config address ahead of time must not block Initialize
+    let (config_account, _) = find_config_address(&program_id);
+    prefund_account(&mut context, &config_account).await;
+
+    let config = FreshConfig {
+        mining_cooldown: 600,
+        lucky_purr_chance: 50,
+        ..FreshConfig::default()
+    };
+    let instruction = create_initialize_instruction(&program_id, &payer.pubkey(), &mint.pubkey(), &spl_token::id(), MrFreshInstruction::Initialize {
+        mining_difficulty: 1000,
+        energy_burst_duration: 100,
+        emission_mode: EmissionMode::Step,
+        proof_required: true,
+        poop_chance: POOP_CHANCE,
+        poop_consolation: true,
+        config: Some(config.clone()),
+    });
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let account = context.banks_client.get_account(config_account).await.unwrap().unwrap();
+    assert_eq!(unpack_account::<FreshConfig>(&account.data).unwrap(), config);
+    let state = verify_mining_result(&mut context.banks_client, &find_state_address(&program_id).0, Some(0))
+        .await
+        .unwrap();
+    assert!(state.proof_required);
+    assert_eq!(state.poop_chance, POOP_CHANCE);
+    assert!(state.poop_consolation);
 }
 
 #[tokio::test]
@@ -211,19 +805,11 @@ async fn test_mining_cooldown() {
     let initial_time = 1000;
     let (mut context, program_id) = setup_test_context(initial_time, 1).await;
     let payer = context.payer.insecure_clone();
-    let miner = Keypair::new();
-
-    let state_account = create_test_state(
-        &mut context.banks_client,
-        &payer,
-        &context.last_blockhash,
-        &program_id,
-        initial_time,
-    ).await.unwrap();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
 
     // First mining attempt
     println!("Debug: Attempting first mine operation");
-    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner);
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
     let result = process_mining_transaction(
         &mut context.banks_client,
         mine_instruction,
@@ -247,7 +833,7 @@ async fn test_mining_cooldown() {
     context.last_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
     
     println!("Debug: Attempting mining during cooldown");
-    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner);
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
     let result = process_mining_transaction(
         &mut context.banks_client,
         mine_instruction,
@@ -275,19 +861,11 @@ async fn test_halving() {
     let mining_difficulty = 1000;
     let (mut context, program_id) = setup_test_context(initial_time, 1).await;
     let payer = context.payer.insecure_clone();
-    let miner = Keypair::new();
-
-    let state_account = create_test_state(
-        &mut context.banks_client,
-        &payer,
-        &context.last_blockhash,
-        &program_id,
-        initial_time,
-    ).await.unwrap();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
 
     // Test mining before first halving
     let initial_reward = calculate_expected_reward(initial_time, initial_time, mining_difficulty);
-    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner);
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
     let result = process_mining_transaction(
         &mut context.banks_client,
         mine_instruction,
@@ -312,7 +890,7 @@ async fn test_halving() {
     let halved_reward = calculate_expected_reward(initial_time, time_after_halving, mining_difficulty);
     assert_eq!(halved_reward, initial_reward / 2, "Halving calculation incorrect");
 
-    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner);
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
     let result = process_mining_transaction(
         &mut context.banks_client,
         mine_instruction,
@@ -321,4 +899,1835 @@ async fn test_halving() {
         context.last_blockhash,
     ).await;
     assert!(result.is_ok(), "Mining after halving failed");
-}
\ No newline at end of file
+
+    // The same miner mining twice is still one unique miner
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.total_miners, 1);
+    assert_eq!(state.total_transactions, 2);
+}
+
+#[tokio::test]
+async fn test_mining_mints_tokens() {
+    println!("\n=== Running Token Minting Test ===");
+    let initial_time = 0;
+    let mining_difficulty = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+
+    // A miner record address funded by someone else must not lock the miner out
+    prefund_account(&mut context, &find_miner_address(&program_id, &miner.pubkey()).0).await;
+
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    let result = process_mining_transaction(
+        &mut context.banks_client,
+        mine_instruction,
+        &payer,
+        &miner,
+        context.last_blockhash,
+    ).await;
+    assert!(result.is_ok(), "Mining attempt failed");
+
+    let expected_reward = calculate_expected_reward(initial_time, initial_time, mining_difficulty);
+    let state = verify_mining_result(&mut context.banks_client, &state_account, Some(expected_reward))
+        .await
+        .unwrap();
+    assert_eq!(get_token_balance(&mut context.banks_client, &miner_token_account).await, expected_reward);
+    assert_eq!(get_miner_record(&mut context.banks_client, &program_id, &miner.pubkey()).await.mine_count, 1);
+
+    let mint_account = context.banks_client.get_account(mint.pubkey()).await.unwrap().unwrap();
+    let mint_state = spl_token::state::Mint::unpack(&mint_account.data).unwrap();
+    assert_eq!(mint_state.supply, state.total_supply, "Mint supply out of sync with state");
+}
+
+#[tokio::test]
+async fn test_mining_mints_token_2022() {
+    println!("\n=== Running Token-2022 Minting Test ===");
+    let initial_time = 0;
+    let mining_difficulty = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token_2022::id()).await;
+
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token_2022::id());
+    let result = process_mining_transaction(
+        &mut context.banks_client,
+        mine_instruction,
+        &payer,
+        &miner,
+        context.last_blockhash,
+    ).await;
+    assert!(result.is_ok(), "Mining attempt failed");
+
+    let expected_reward = calculate_expected_reward(initial_time, initial_time, mining_difficulty);
+    let state = verify_mining_result(&mut context.banks_client, &state_account, Some(expected_reward))
+        .await
+        .unwrap();
+    assert_eq!(get_token_balance(&mut context.banks_client, &miner_token_account).await, expected_reward);
+
+    let mint_account = context.banks_client.get_account(mint.pubkey()).await.unwrap().unwrap();
+    let mint_state = spl_token::state::Mint::unpack(&mint_account.data).unwrap();
+    assert_eq!(mint_state.supply, state.total_supply, "Mint supply out of sync with state");
+    assert_eq!(state.token_program, spl_token_2022::id());
+}
+
+#[tokio::test]
+async fn test_create_mint_with_metadata() {
+    println!("\n=== Running Create Mint Test ===");
+    let (mut context, program_id) = setup_test_context(0, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (mint, _) = find_mint_address(&program_id);
+    let (mint_authority, _) = find_mint_authority_address(&program_id);
+    let impostor = create_funded_miner(&mut context.banks_client, &payer, &context.last_blockhash)
+        .await
+        .unwrap();
+
+    // The metadata is permanent, so only the upgrade authority may create the mint
+    let instruction = create_mint_instruction(&program_id, &impostor.pubkey());
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer, &impostor], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    // Lamports sent to the mint address ahead of time must not block creation
+    prefund_account(&mut context, &mint).await;
+
+    let instruction = create_mint_instruction(&program_id, &payer.pubkey());
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &[&payer],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let mint_account = context.banks_client.get_account(mint).await.unwrap().unwrap();
+    assert_eq!(mint_account.owner, spl_token_2022::id());
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data).unwrap();
+    assert_eq!(mint_state.base.decimals, FRESH_DECIMALS);
+    assert_eq!(mint_state.base.mint_authority, Some(mint_authority).into());
+    assert!(mint_state.base.freeze_authority.is_none(), "Freeze authority should be revoked");
+
+    let metadata = mint_state.get_variable_len_extension::<TokenMetadata>().unwrap();
+    assert_eq!(metadata.name, "Mr. Fresh");
+    assert_eq!(metadata.symbol, "FRESH");
+    assert_eq!(metadata.mint, mint);
+
+    // The program-created mint is accepted by Initialize
+    create_test_state(
+        &mut context.banks_client,
+        &payer,
+        &context.last_blockhash,
+        &program_id,
+        &mint,
+        &spl_token_2022::id(),
+    ).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cooldown_is_per_miner() {
+    println!("\n=== Running Per-Miner Cooldown Test ===");
+    let initial_time = 1000;
+    let mining_difficulty = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+    let other_miner = create_funded_miner(&mut context.banks_client, &payer, &context.last_blockhash)
+        .await
+        .unwrap();
+    let other_token_account = create_token_account(
+        &mut context.banks_client,
+        &payer,
+        &context.last_blockhash,
+        &mint.pubkey(),
+        &other_miner.pubkey(),
+        &spl_token::id(),
+    ).await.unwrap();
+
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    process_mining_transaction(&mut context.banks_client, mine_instruction, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+
+    // A second miner is not locked out by the first miner's nap
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &other_miner, &mint.pubkey(), &other_token_account, &spl_token::id());
+    let result = process_mining_transaction(
+        &mut context.banks_client,
+        mine_instruction,
+        &payer,
+        &other_miner,
+        context.last_blockhash,
+    ).await;
+    assert!(result.is_ok(), "Second miner should not share the first miner's cooldown");
+
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.total_miners, 2);
+
+    let expected_reward = calculate_expected_reward(initial_time, initial_time, mining_difficulty);
+    let record = get_miner_record(&mut context.banks_client, &program_id, &miner.pubkey()).await;
+    assert_eq!(record.miner, miner.pubkey());
+    assert_eq!(record.last_mining_timestamp, initial_time);
+    assert_eq!(record.total_rewards, expected_reward);
+    assert_eq!(record.mine_count, 1);
+
+    // The first miner is still napping
+    context.set_sysvar(&Clock {
+        slot: 2,
+        epoch_start_timestamp: initial_time,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: initial_time + 60,
+    });
+    context.last_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    let result = process_mining_transaction(
+        &mut context.banks_client,
+        mine_instruction,
+        &payer,
+        &miner,
+        context.last_blockhash,
+    ).await;
+    assert!(
+        matches!(
+            result,
+            Err(BanksClientError::TransactionError(
+                TransactionError::InstructionError(_, InstructionError::Custom(err))
+            )) if err == FreshError::CooldownActive as u32
+        ),
+        "Expected cooldown error, got: {:?}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn test_mining_requires_verified_miner() {
+    println!("\n=== Running Miner Verification Test ===");
+    let initial_time = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+    let payer_token_account = create_token_account(
+        &mut context.banks_client,
+        &payer,
+        &context.last_blockhash,
+        &mint.pubkey(),
+        &payer.pubkey(),
+        &spl_token::id(),
+    ).await.unwrap();
+
+    // Submitting Mine on behalf of a miner who did not sign
+    let mut mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    mine_instruction.accounts[1].is_signer = false;
+    let transaction = Transaction::new_signed_with_payer(
+        &[mine_instruction],
+        Some(&payer.pubkey()),
+        &[&payer],
+        context.last_blockhash,
+    );
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::MinerMustSign);
+
+    // Directing the reward to someone else's token account
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &payer_token_account, &spl_token::id());
+    let result = process_mining_transaction(
+        &mut context.banks_client,
+        mine_instruction,
+        &payer,
+        &miner,
+        context.last_blockhash,
+    ).await;
+    assert_fresh_error(result, FreshError::TokenAccountMismatch);
+
+    // A read-only miner record is rejected
+    let mut mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    mine_instruction.accounts[7].is_writable = false;
+    let result = process_mining_transaction(
+        &mut context.banks_client,
+        mine_instruction,
+        &payer,
+        &miner,
+        context.last_blockhash,
+    ).await;
+    assert_fresh_error(result, FreshError::AccountNotWritable);
+
+    // So is a state account some other program owns
+    let mine_instruction = create_mine_instruction(&program_id, &payer_token_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    let result = process_mining_transaction(
+        &mut context.banks_client,
+        mine_instruction,
+        &payer,
+        &miner,
+        context.last_blockhash,
+    ).await;
+    assert_fresh_error(result, FreshError::InvalidAccountOwner);
+}
+
+#[tokio::test]
+async fn test_update_difficulty_requires_authority() {
+    println!("\n=== Running Update Difficulty Authority Test ===");
+    let (mut context, program_id) = setup_test_context(0, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (impostor, _, _, state_account) = setup_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+
+    let instruction = create_update_difficulty_instruction(&program_id, &state_account, &impostor.pubkey(), &payer.pubkey(), 0, 5000);
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &[&payer, &impostor],
+        context.last_blockhash,
+    );
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    let instruction = create_update_difficulty_instruction(&program_id, &state_account, &payer.pubkey(), &payer.pubkey(), 0, 5000);
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &[&payer],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    // The change is only queued until the timelock passes
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.mining_difficulty, 1000);
+    assert_eq!(state.next_change_id, 1);
+}
+
+#[tokio::test]
+async fn test_authority_handover_and_renounce() {
+    println!("\n=== Running Authority Handover Test ===");
+    let (mut context, program_id) = setup_test_context(0, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (new_authority, _, _, state_account) = setup_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+    let impostor = create_funded_miner(&mut context.banks_client, &payer, &context.last_blockhash)
+        .await
+        .unwrap();
+
+    let propose = create_authority_instruction(
+        &program_id,
+        &state_account,
+        &payer.pubkey(),
+        MrFreshInstruction::ProposeAuthority { new_authority: new_authority.pubkey() },
+    );
+    let transaction = Transaction::new_signed_with_payer(&[propose], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    // Only the proposed key can complete the handover
+    let accept = create_authority_instruction(&program_id, &state_account, &impostor.pubkey(), MrFreshInstruction::AcceptAuthority);
+    let transaction = Transaction::new_signed_with_payer(&[accept], Some(&payer.pubkey()), &[&payer, &impostor], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    let accept = create_authority_instruction(&program_id, &state_account, &new_authority.pubkey(), MrFreshInstruction::AcceptAuthority);
+    let transaction = Transaction::new_signed_with_payer(&[accept], Some(&payer.pubkey()), &[&payer, &new_authority], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.authority, new_authority.pubkey());
+    assert_eq!(state.pending_authority, Pubkey::default());
+
+    // The previous authority lost its powers
+    let instruction = create_update_difficulty_instruction(&program_id, &state_account, &payer.pubkey(), &payer.pubkey(), 0, 5000);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    // Renouncing while paused would leave mining paused for good
+    let pause = create_authority_instruction(&program_id, &state_account, &new_authority.pubkey(), MrFreshInstruction::Pause);
+    let transaction = Transaction::new_signed_with_payer(&[pause], Some(&payer.pubkey()), &[&payer, &new_authority], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    let renounce = create_authority_instruction(&program_id, &state_account, &new_authority.pubkey(), MrFreshInstruction::RenounceAuthority);
+    let transaction = Transaction::new_signed_with_payer(&[renounce], Some(&payer.pubkey()), &[&payer, &new_authority], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Paused);
+
+    let unpause = create_authority_instruction(&program_id, &state_account, &new_authority.pubkey(), MrFreshInstruction::Unpause);
+    let transaction = Transaction::new_signed_with_payer(&[unpause], Some(&payer.pubkey()), &[&payer, &new_authority], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    context.last_blockhash = context.banks_client.get_new_latest_blockhash(&context.last_blockhash).await.unwrap();
+    let renounce = create_authority_instruction(&program_id, &state_account, &new_authority.pubkey(), MrFreshInstruction::RenounceAuthority);
+    let transaction = Transaction::new_signed_with_payer(&[renounce], Some(&payer.pubkey()), &[&payer, &new_authority], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    assert!(!verify_mining_result(&mut context.banks_client, &state_account, None).await.unwrap().paused);
+
+    let instruction = create_update_difficulty_instruction(&program_id, &state_account, &new_authority.pubkey(), &payer.pubkey(), 0, 5000);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer, &new_authority], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::AuthorityRenounced);
+}
+
+#[tokio::test]
+async fn test_renounce_releases_queued_changes() {
+    println!("\n=== Running Renounce With Queued Change Test ===");
+    let (mut context, program_id) = setup_test_context(0, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (rent_payer, _, _, state_account) = setup_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+    let stranger = create_funded_miner(&mut context.banks_client, &payer, &context.last_blockhash)
+        .await
+        .unwrap();
+
+    let queue = create_update_difficulty_instruction(&program_id, &state_account, &payer.pubkey(), &rent_payer.pubkey(), 0, 5000);
+    let renounce = create_authority_instruction(&program_id, &state_account, &payer.pubkey(), MrFreshInstruction::RenounceAuthority);
+    let transaction = Transaction::new_signed_with_payer(&[queue, renounce], Some(&payer.pubkey()), &[&payer, &rent_payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    // Nobody can execute the change any more
+    context.set_sysvar(&Clock { unix_timestamp: TIMELOCK_DELAY, ..Clock::default() });
+    let execute = create_resolve_change_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, MrFreshInstruction::ExecuteQueuedChange);
+    let transaction = Transaction::new_signed_with_payer(&[execute], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::AuthorityRenounced);
+
+    // So anyone may close it, but the rent still goes back to its payer
+    let cancel = create_resolve_change_instruction(&program_id, &stranger.pubkey(), &stranger.pubkey(), 0, MrFreshInstruction::CancelQueuedChange);
+    let transaction = Transaction::new_signed_with_payer(&[cancel], Some(&payer.pubkey()), &[&payer, &stranger], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert!(
+        matches!(
+            result,
+            Err(BanksClientError::TransactionError(TransactionError::InstructionError(_, InstructionError::InvalidArgument)))
+        ),
+        "Expected rent receiver mismatch, got: {:?}",
+        result
+    );
+
+    let balance_before = context.banks_client.get_balance(rent_payer.pubkey()).await.unwrap();
+    let cancel = create_resolve_change_instruction(&program_id, &stranger.pubkey(), &rent_payer.pubkey(), 0, MrFreshInstruction::CancelQueuedChange);
+    let transaction = Transaction::new_signed_with_payer(&[cancel], Some(&payer.pubkey()), &[&payer, &stranger], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    let (queued_address, _) = find_queued_change_address(&program_id, 0);
+    assert!(context.banks_client.get_account(queued_address).await.unwrap().is_none());
+    let rent = context.banks_client.get_rent().await.unwrap();
+    assert_eq!(
+        context.banks_client.get_balance(rent_payer.pubkey()).await.unwrap(),
+        balance_before + rent.minimum_balance(QueuedChange::LEN)
+    );
+}
+
+#[tokio::test]
+async fn test_reinitialization_preserves_supply() {
+    println!("\n=== Running Re-initialization Test ===");
+    let initial_time = 1000;
+    let mining_difficulty = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    process_mining_transaction(&mut context.banks_client, mine_instruction, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+    let expected_supply = calculate_expected_reward(initial_time, initial_time, mining_difficulty);
+
+    // Replay Initialize later on, even as the upgrade authority
+    context.set_sysvar(&Clock {
+        slot: 2,
+        epoch_start_timestamp: initial_time,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: initial_time + HALVING_INTERVAL,
+    });
+    context.last_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+
+    let instruction = create_initialize_instruction(
+        &program_id,
+        &payer.pubkey(),
+        &mint.pubkey(),
+        &spl_token::id(),
+        MrFreshInstruction::Initialize {
+            mining_difficulty: MIN_DIFFICULTY,
+            energy_burst_duration: 1,
+            emission_mode: EmissionMode::Step,
+            proof_required: false,
+            poop_chance: 0,
+            poop_consolation: false,
+            config: None,
+        },
+    );
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&payer.pubkey()),
+        &[&payer],
+        context.last_blockhash,
+    );
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::AlreadyInitialized);
+
+    let state = verify_mining_result(&mut context.banks_client, &state_account, Some(expected_supply))
+        .await
+        .unwrap();
+    assert_eq!(state.initialization_timestamp, initial_time);
+    assert_eq!(state.mining_difficulty, mining_difficulty);
+}
+
+fn sample_state(authority: &Pubkey) -> MrFreshState {
+    MrFreshState {
+        version: STATE_VERSION,
+        total_supply: 123_456,
+        mining_difficulty: 2000,
+        last_mining_timestamp: 5000,
+        total_miners: 7,
+        total_transactions: 42,
+        last_energy_burst_slot: 41,
+        energy_burst_duration: 100,
+        initialization_timestamp: 1000,
+        mint: Pubkey::new_unique(),
+        mint_authority_bump: 254,
+        token_program: spl_token::id(),
+        authority: *authority,
+        pending_authority: Pubkey::default(),
+        is_initialized: true,
+        next_change_id: 0,
+        paused: false,
+        retarget_window_start: 5000,
+        poop_chance: 0,
+        poop_consolation: false,
+        emission_mode: EmissionMode::Step,
+        proof_required: true,
+        challenge: [6; 32],
+    }
+}
+
+fn create_migrate_instruction(program_id: &Pubkey, payer: &Pubkey) -> Instruction {
+    let mut buffer = Vec::new();
+    MrFreshInstruction::Migrate.serialize(&mut buffer).unwrap();
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(find_state_address(program_id).0, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(solana_system_interface::program::ID, false),
+            AccountMeta::new(find_config_address(program_id).0, false),
+        ],
+        data: buffer,
+    }
+}
+
+#[tokio::test]
+async fn test_migrate_current_state_only() {
+    println!("\n=== Running State Migration Test ===");
+    let initial_time = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (_, _, _, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+    let before = context.banks_client.get_account(state_account).await.unwrap().unwrap();
+    assert_eq!(state_version(&before.data), Some(STATE_VERSION));
+
+    // A current state has nothing to upgrade
+    let migrate = create_migrate_instruction(&program_id, &payer.pubkey());
+    let transaction = Transaction::new_signed_with_payer(&[migrate], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    assert_eq!(context.banks_client.get_account(state_account).await.unwrap().unwrap().data, before.data);
+
+    // The original 64-byte state carries no mint or authority, so it needs a
+    // fresh deploy rather than Migrate
+    context.set_account(&state_account, &Account {
+        lamports: 1_000_000_000,
+        data: vec![1; 64],
+        owner: program_id,
+        executable: false,
+        rent_epoch: 0,
+    }.into());
+    context.last_blockhash = context.banks_client.get_new_latest_blockhash(&context.last_blockhash).await.unwrap();
+    let migrate = create_migrate_instruction(&program_id, &payer.pubkey());
+    let transaction = Transaction::new_signed_with_payer(&[migrate], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert!(
+        matches!(
+            result,
+            Err(BanksClientError::TransactionError(TransactionError::InstructionError(_, InstructionError::InvalidAccountData)))
+        ),
+        "Expected the original layout to be refused, got: {:?}",
+        result
+    );
+}
+
+#[test]
+fn test_account_discriminators() {
+    fn expected(type_name: &str) -> [u8; DISCRIMINATOR_LEN] {
+        let hash = solana_program::hash::hash(format!("account:{}", type_name).as_bytes());
+        hash.to_bytes()[..DISCRIMINATOR_LEN].try_into().unwrap()
+    }
+    assert_eq!(MrFreshState::DISCRIMINATOR, expected("MrFreshState"));
+    assert_eq!(MinerAccount::DISCRIMINATOR, expected("MinerAccount"));
+    assert_eq!(FreshConfig::DISCRIMINATOR, expected("FreshConfig"));
+    assert_eq!(QueuedChange::DISCRIMINATOR, expected("QueuedChange"));
+    assert_eq!(Multisig::DISCRIMINATOR, expected("Multisig"));
+    assert_eq!(MineCommitment::DISCRIMINATOR, expected("MineCommitment"));
+}
+
+#[tokio::test]
+async fn test_account_type_confusion_rejected() {
+    println!("\n=== Running Account Type Confusion Test ===");
+    let initial_time = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+
+    // Mine once so there is a program-owned miner record
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    process_mining_transaction(&mut context.banks_client, mine_instruction, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+
+    // Passing the miner record where the state is expected must not be trusted
+    let (miner_record, _) = find_miner_address(&program_id, &miner.pubkey());
+    let instruction = create_update_difficulty_instruction(&program_id, &miner_record, &payer.pubkey(), &payer.pubkey(), 0, 5000);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::InvalidAccountType);
+}
+
+#[test]
+fn test_state_view_matches_borsh_layout() {
+    let authority = Pubkey::new_unique();
+    let state = sample_state(&authority);
+    let mut data = MrFreshState::DISCRIMINATOR.to_vec();
+    state.serialize(&mut data).unwrap();
+
+    let view = bytemuck::from_bytes_mut::<MrFreshStateView>(&mut data);
+    assert_eq!(view.discriminator, MrFreshState::DISCRIMINATOR);
+    assert_eq!(view.version, STATE_VERSION);
+    assert_eq!(u64::from(view.total_supply), state.total_supply);
+    assert_eq!(u64::from(view.mining_difficulty), state.mining_difficulty);
+    assert_eq!(i64::from(view.initialization_timestamp), state.initialization_timestamp);
+    assert_eq!(view.mint, state.mint);
+    assert_eq!(view.mint_authority_bump, state.mint_authority_bump);
+    assert_eq!(view.authority, authority);
+    assert!(bool::from(view.is_initialized));
+
+    // Writes through the view must decode the same way through Borsh
+    view.total_supply = 999.into();
+    view.last_mining_timestamp = (-1i64).into();
+    view.total_miners = 8.into();
+    let updated = unpack_account::<MrFreshState>(&data).unwrap();
+    assert_eq!(updated.total_supply, 999);
+    assert_eq!(updated.last_mining_timestamp, -1);
+    assert_eq!(updated.total_miners, 8);
+    assert_eq!(updated.token_program, state.token_program);
+    assert!(updated.is_initialized);
+
+    // The conversions the borsh-mine build uses agree with both encodings
+    assert_eq!(bytemuck::bytes_of(&MrFreshStateView::from(&updated)), &data[..]);
+    let mut round_trip = MrFreshState::DISCRIMINATOR.to_vec();
+    MrFreshState::from(bytemuck::from_bytes::<MrFreshStateView>(&data)).serialize(&mut round_trip).unwrap();
+    assert_eq!(round_trip, data);
+}
+
+#[test]
+fn test_config_and_miner_views_match_borsh_layout() {
+    let config = FreshConfig { mining_cooldown: -5, max_supply: 77, ..FreshConfig::default() };
+    let mut data = FreshConfig::DISCRIMINATOR.to_vec();
+    config.serialize(&mut data).unwrap();
+    assert_eq!(FreshConfig::from(bytemuck::from_bytes::<FreshConfigView>(&data)), config);
+
+    let miner = Pubkey::new_unique();
+    let record = MinerAccount { miner, last_mining_timestamp: 1234, total_rewards: 5678, mine_count: 9, bump: 251 };
+    let mut data = MinerAccount::DISCRIMINATOR.to_vec();
+    record.serialize(&mut data).unwrap();
+    let view = bytemuck::from_bytes_mut::<MinerAccountView>(&mut data);
+    assert_eq!(view.miner, miner);
+    assert_eq!(i64::from(view.last_mining_timestamp), 1234);
+    assert_eq!(view.bump, 251);
+
+    view.mine_count = 10.into();
+    view.total_rewards = 6000.into();
+    let updated = unpack_account::<MinerAccount>(&data).unwrap();
+    assert_eq!(updated.mine_count, 10);
+    assert_eq!(updated.total_rewards, 6000);
+    assert_eq!(updated.bump, 251);
+    assert_eq!(bytemuck::bytes_of(&MinerAccountView::from(&updated)), &data[..]);
+    let mut round_trip = MinerAccount::DISCRIMINATOR.to_vec();
+    MinerAccount::from(bytemuck::from_bytes::<MinerAccountView>(&data)).serialize(&mut round_trip).unwrap();
+    assert_eq!(round_trip, data);
+}
+
+// Compute units are only metered on SBF, so this runs under `cargo test-sbf`.
+// The baseline is a second build of the program with the borsh-mine feature,
+// whose Mine decodes and re-encodes its accounts with Borsh.
+#[cfg(feature = "test-sbf")]
+fn build_borsh_mine_program() -> Vec<u8> {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let out_dir = format!("{}/target/deploy/borsh-mine", manifest_dir);
+    let status = std::process::Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+        .args(["build-sbf", "--features", "borsh-mine", "--sbf-out-dir", &out_dir])
+        // Its own target directory, the running test holds the usual one
+        .env("CARGO_TARGET_DIR", format!("{}/target/borsh-mine", manifest_dir))
+        .current_dir(manifest_dir)
+        .status()
+        .expect("Failed to run cargo build-sbf");
+    assert!(status.success(), "Building the borsh-mine program failed");
+    std::fs::read(format!("{}/mr_fresh.so", out_dir)).unwrap()
+}
+
+// Mines twice with a fresh miner and returns what the second Mine cost
+#[cfg(feature = "test-sbf")]
+async fn measure_mine(context: &mut ProgramTestContext, program_id: &Pubkey, initial_time: i64, slot: u64) -> u64 {
+    let payer = context.payer.insecure_clone();
+    set_upgrade_authority(context, program_id, &payer.pubkey()).await;
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(context, program_id, &spl_token::id()).await;
+
+    // First mine pays for the miner record; measure the steady-state call
+    let mine_instruction = create_mine_instruction(program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    process_mining_transaction(&mut context.banks_client, mine_instruction.clone(), &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+
+    warp_with_slot_hashes(context, slot, initial_time + MINING_COOLDOWN, &[]).await;
+    let transaction = Transaction::new_signed_with_payer(
+        &[mine_instruction],
+        Some(&payer.pubkey()),
+        &[&payer, &miner],
+        context.last_blockhash,
+    );
+    let result = context.banks_client.process_transaction_with_metadata(transaction).await.unwrap();
+    assert!(result.result.is_ok(), "Mining attempt failed: {:?}", result.result);
+    result.metadata.unwrap().compute_units_consumed
+}
+
+#[cfg(feature = "test-sbf")]
+#[tokio::test]
+async fn test_mining_compute_units() {
+    println!("\n=== Running Mining Compute Units Test ===");
+    let initial_time = 0;
+    let program_id = Pubkey::new_unique();
+    let borsh_program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("mr_fresh", program_id, None);
+    program_test.prefer_bpf(true);
+    let borsh_program = build_borsh_mine_program();
+    program_test.add_account(borsh_program_id, Account {
+        lamports: Rent::default().minimum_balance(borsh_program.len()).max(1),
+        data: borsh_program,
+        owner: solana_sdk_ids::bpf_loader::id(),
+        executable: true,
+        rent_epoch: 0,
+    });
+    let mut context = program_test.start_with_context().await;
+    context.set_sysvar(&Clock {
+        slot: 1,
+        epoch_start_timestamp: initial_time,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: initial_time,
+    });
+
+    let compute_units = measure_mine(&mut context, &program_id, initial_time, 3).await;
+    let borsh_compute_units = measure_mine(&mut context, &borsh_program_id, initial_time + MINING_COOLDOWN, 5).await;
+    println!("Mine used {} compute units, {} with Borsh account access", compute_units, borsh_compute_units);
+    assert!(
+        compute_units < borsh_compute_units,
+        "Zero-copy Mine ({} compute units) should beat the Borsh build ({})",
+        compute_units,
+        borsh_compute_units
+    );
+}
+
+#[test]
+fn test_config_bounds() {
+    let invalid = || Err(FreshError::InvalidConfig.into());
+    assert_eq!(FreshConfig::default().validate(0), Ok(()));
+    let config = FreshConfig {
+        halving_interval: MIN_HALVING_INTERVAL,
+        max_supply: MAX_SUPPLY,
+        initial_base_reward: MAX_BASE_REWARD,
+        ..FreshConfig::default()
+    };
+    assert_eq!(config.validate(0), Ok(()));
+
+    let out_of_range = [
+        FreshConfig { max_supply: MAX_SUPPLY + 1, ..FreshConfig::default() },
+        FreshConfig { max_supply: u64::MAX, ..FreshConfig::default() },
+        FreshConfig { initial_base_reward: 0, ..FreshConfig::default() },
+        FreshConfig { initial_base_reward: MAX_BASE_REWARD + 1, ..FreshConfig::default() },
+        FreshConfig { max_supply: INITIAL_BASE_REWARD - 1, ..FreshConfig::default() },
+        FreshConfig { halving_interval: 0, ..FreshConfig::default() },
+        FreshConfig { halving_interval: MIN_HALVING_INTERVAL - 1, ..FreshConfig::default() },
+        FreshConfig { halving_interval: MAX_HALVING_INTERVAL + 1, ..FreshConfig::default() },
+        FreshConfig { halving_interval: i64::MAX, ..FreshConfig::default() },
+    ];
+    for config in out_of_range {
+        assert_eq!(config.validate(0), invalid(), "{:?}", config);
+    }
+
+    // Supply already minted still sets the floor
+    let config = FreshConfig { max_supply: 1_000_000_000, ..FreshConfig::default() };
+    assert_eq!(config.validate(1_000_000_000), Ok(()));
+    assert_eq!(config.validate(1_000_000_001), invalid());
+}
+
+#[tokio::test]
+async fn test_update_config() {
+    println!("\n=== Running Config Update Test ===");
+    let initial_time = 1000;
+    let mining_difficulty = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+
+    let config = FreshConfig {
+        mining_cooldown: 0,
+        initial_base_reward: 20_000_000,
+        ..FreshConfig::default()
+    };
+
+    // Only the authority may change the economics
+    let instruction = create_update_config_instruction(&program_id, &miner.pubkey(), &payer.pubkey(), 0, config.clone());
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer, &miner], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    // A zero purr chance would divide by zero while mining
+    let bad_config = FreshConfig { lucky_purr_chance: 0, ..config.clone() };
+    let instruction = create_update_config_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, bad_config);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::InvalidConfig);
+
+    // Not even the authority can lift the hard cap
+    let bad_config = FreshConfig { max_supply: u64::MAX, ..config.clone() };
+    let instruction = create_update_config_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, bad_config);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::InvalidConfig);
+
+    let instruction = create_update_config_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, config.clone());
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    context.set_sysvar(&Clock {
+        slot: 1,
+        epoch_start_timestamp: initial_time,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: initial_time + TIMELOCK_DELAY,
+    });
+    // Only the config PDA may be overwritten
+    let mut execute = create_resolve_change_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, MrFreshInstruction::ExecuteQueuedChange);
+    execute.accounts.push(AccountMeta::new(state_account, false));
+    let transaction = Transaction::new_signed_with_payer(&[execute], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert!(
+        matches!(
+            result,
+            Err(BanksClientError::TransactionError(TransactionError::InstructionError(_, InstructionError::InvalidSeeds)))
+        ),
+        "Expected config PDA mismatch, got: {:?}",
+        result
+    );
+
+    let execute = with_config_account(create_resolve_change_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, MrFreshInstruction::ExecuteQueuedChange));
+    let transaction = Transaction::new_signed_with_payer(&[execute], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    let config_account = context.banks_client.get_account(find_config_address(&program_id).0).await.unwrap().unwrap();
+    assert_eq!(unpack_account::<FreshConfig>(&config_account.data).unwrap(), config);
+
+    // With no cooldown the miner can mine twice in a row at the new base reward
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    let transaction = Transaction::new_signed_with_payer(
+        &[mine_instruction.clone(), mine_instruction],
+        Some(&payer.pubkey()),
+        &[&payer, &miner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let expected_reward = single_transaction_reward(config.initial_base_reward / mining_difficulty, &config, 0, true);
+    verify_mining_result(&mut context.banks_client, &state_account, Some(2 * expected_reward))
+        .await
+        .unwrap();
+    assert_eq!(get_miner_record(&mut context.banks_client, &program_id, &miner.pubkey()).await.mine_count, 2);
+}
+
+#[tokio::test]
+async fn test_timelocked_changes() {
+    println!("\n=== Running Timelocked Changes Test ===");
+    let initial_time = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (rent_payer, _, _, state_account) = setup_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+
+    // Funding the next change's address ahead of time must not block queueing
+    let (queued_address, _) = find_queued_change_address(&program_id, 0);
+    prefund_account(&mut context, &queued_address).await;
+
+    // Queue two changes, the second paid for by a separate rent payer
+    let queue_first = create_update_difficulty_instruction(&program_id, &state_account, &payer.pubkey(), &payer.pubkey(), 0, 5000);
+    let queue_second = create_update_difficulty_instruction(&program_id, &state_account, &payer.pubkey(), &rent_payer.pubkey(), 1, 7000);
+    let transaction = Transaction::new_signed_with_payer(
+        &[queue_first, queue_second],
+        Some(&payer.pubkey()),
+        &[&payer, &rent_payer],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let account = context.banks_client.get_account(queued_address).await.unwrap().unwrap();
+    let queued_change = unpack_account::<QueuedChange>(&account.data).unwrap();
+    assert_eq!(queued_change.id, 0);
+    assert_eq!(queued_change.effective_at, initial_time + TIMELOCK_DELAY);
+    assert_eq!(queued_change.change, ParameterChange::Difficulty { new_difficulty: 5000 });
+
+    // Too early
+    let execute = create_resolve_change_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, MrFreshInstruction::ExecuteQueuedChange);
+    let transaction = Transaction::new_signed_with_payer(std::slice::from_ref(&execute), Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::TimelockActive);
+
+    // Cancelling refunds the rent to whoever paid for the queued change
+    let cancel = create_resolve_change_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 1, MrFreshInstruction::CancelQueuedChange);
+    let transaction = Transaction::new_signed_with_payer(&[cancel], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert!(
+        matches!(
+            result,
+            Err(BanksClientError::TransactionError(TransactionError::InstructionError(_, InstructionError::InvalidArgument)))
+        ),
+        "Expected rent receiver mismatch, got: {:?}",
+        result
+    );
+    let balance_before = context.banks_client.get_balance(rent_payer.pubkey()).await.unwrap();
+    let cancel = create_resolve_change_instruction(&program_id, &payer.pubkey(), &rent_payer.pubkey(), 1, MrFreshInstruction::CancelQueuedChange);
+    let transaction = Transaction::new_signed_with_payer(&[cancel], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    let (cancelled_address, _) = find_queued_change_address(&program_id, 1);
+    assert!(context.banks_client.get_account(cancelled_address).await.unwrap().is_none());
+    let rent = context.banks_client.get_rent().await.unwrap();
+    assert_eq!(
+        context.banks_client.get_balance(rent_payer.pubkey()).await.unwrap(),
+        balance_before + rent.minimum_balance(QueuedChange::LEN)
+    );
+
+    // A fresh blockhash so the retried execute is a new transaction
+    context.warp_to_slot(2).unwrap();
+    context.set_sysvar(&Clock {
+        slot: 2,
+        epoch_start_timestamp: initial_time,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: initial_time + TIMELOCK_DELAY,
+    });
+    context.last_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(&[execute], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.mining_difficulty, 5000);
+    assert!(context.banks_client.get_account(queued_address).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_pause_blocks_mining() {
+    println!("\n=== Running Pause Test ===");
+    let initial_time = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+
+    // Only the authority can pull the brake
+    let pause = create_authority_instruction(&program_id, &state_account, &miner.pubkey(), MrFreshInstruction::Pause);
+    let transaction = Transaction::new_signed_with_payer(&[pause], Some(&payer.pubkey()), &[&payer, &miner], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    let pause = create_authority_instruction(&program_id, &state_account, &payer.pubkey(), MrFreshInstruction::Pause);
+    let transaction = Transaction::new_signed_with_payer(&[pause], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    let result = process_mining_transaction(
+        &mut context.banks_client,
+        mine_instruction.clone(),
+        &payer,
+        &miner,
+        context.last_blockhash,
+    ).await;
+    assert_fresh_error(result, FreshError::Paused);
+
+    // State stays readable while paused
+    let state = verify_mining_result(&mut context.banks_client, &state_account, Some(0))
+        .await
+        .unwrap();
+    assert!(state.paused);
+
+    let unpause = create_authority_instruction(&program_id, &state_account, &payer.pubkey(), MrFreshInstruction::Unpause);
+    let transaction = Transaction::new_signed_with_payer(&[unpause], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    // A fresh blockhash so the retried mine is a new transaction
+    context.warp_to_slot(2).unwrap();
+    context.set_sysvar(&Clock {
+        slot: 2,
+        epoch_start_timestamp: initial_time,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: initial_time,
+    });
+    context.last_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    process_mining_transaction(&mut context.banks_client, mine_instruction, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert!(!state.paused);
+    assert_eq!(state.total_transactions, 1);
+}
+
+#[tokio::test]
+async fn test_multisig_authority() {
+    println!("\n=== Running Multisig Authority Test ===");
+    let (mut context, program_id) = setup_test_context(1000, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (_, _, _, state_account) = setup_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+
+    let (alice, bob, carol) = (Keypair::new(), Keypair::new(), Keypair::new());
+    let owners = vec![alice.pubkey(), bob.pubkey(), carol.pubkey()];
+
+    // A threshold above the signer count could never be met
+    let bad_multisig = Keypair::new();
+    let instruction = create_multisig_instruction(&program_id, &bad_multisig.pubkey(), &payer.pubkey(), 4, owners.clone());
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer, &bad_multisig], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::InvalidMultisig);
+
+    let multisig = Keypair::new();
+    let instruction = create_multisig_instruction(&program_id, &multisig.pubkey(), &payer.pubkey(), 2, owners);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer, &multisig], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    let account = context.banks_client.get_account(multisig.pubkey()).await.unwrap().unwrap();
+    let stored = unpack_account::<Multisig>(&account.data).unwrap();
+    assert_eq!(stored.threshold, 2);
+    assert_eq!(stored.signers(), &[alice.pubkey(), bob.pubkey(), carol.pubkey()]);
+
+    // Hand the authority over to the multisig, which accepts with 2 of 3
+    let propose = create_authority_instruction(
+        &program_id,
+        &state_account,
+        &payer.pubkey(),
+        MrFreshInstruction::ProposeAuthority { new_authority: multisig.pubkey() },
+    );
+    let transaction = Transaction::new_signed_with_payer(&[propose], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let accept = create_authority_instruction(&program_id, &state_account, &payer.pubkey(), MrFreshInstruction::AcceptAuthority);
+    let transaction = Transaction::new_signed_with_payer(
+        &[with_multisig_signers(accept.clone(), &multisig.pubkey(), &[&alice])],
+        Some(&payer.pubkey()),
+        &[&payer, &alice],
+        context.last_blockhash,
+    );
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[with_multisig_signers(accept, &multisig.pubkey(), &[&alice, &bob])],
+        Some(&payer.pubkey()),
+        &[&payer, &alice, &bob],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.authority, multisig.pubkey());
+
+    // The same signer listed twice still counts once
+    let pause = create_authority_instruction(&program_id, &state_account, &payer.pubkey(), MrFreshInstruction::Pause);
+    let transaction = Transaction::new_signed_with_payer(
+        &[with_multisig_signers(pause.clone(), &multisig.pubkey(), &[&carol, &carol])],
+        Some(&payer.pubkey()),
+        &[&payer, &carol],
+        context.last_blockhash,
+    );
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    // Nor can the keypair that created the multisig act on its own
+    let pause_by_creator = create_authority_instruction(&program_id, &state_account, &multisig.pubkey(), MrFreshInstruction::Pause);
+    let transaction = Transaction::new_signed_with_payer(&[pause_by_creator], Some(&payer.pubkey()), &[&payer, &multisig], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::Unauthorized);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[with_multisig_signers(pause, &multisig.pubkey(), &[&alice, &carol])],
+        Some(&payer.pubkey()),
+        &[&payer, &alice, &carol],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    // Parameter updates take the multisig too, with the payer covering rent
+    let queue = create_update_difficulty_instruction(&program_id, &state_account, &payer.pubkey(), &payer.pubkey(), 0, 5000);
+    let transaction = Transaction::new_signed_with_payer(
+        &[with_multisig_signers(queue, &multisig.pubkey(), &[&bob, &carol])],
+        Some(&payer.pubkey()),
+        &[&payer, &bob, &carol],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await.unwrap();
+
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert!(state.paused);
+    assert_eq!(state.next_change_id, 1);
+}
+
+#[test]
+fn test_retarget_difficulty() {
+    let target_timespan = RETARGET_WINDOW as i64 * TARGET_MINE_INTERVAL;
+    // On schedule leaves difficulty alone
+    assert_eq!(retarget_difficulty(1000, target_timespan), 1000);
+    // Twice as fast doubles it, twice as slow halves it
+    assert_eq!(retarget_difficulty(1000, target_timespan / 2), 2000);
+    assert_eq!(retarget_difficulty(1000, target_timespan * 2), 500);
+    // Moves are capped per window, even for a burst of mines in one second
+    assert_eq!(retarget_difficulty(1000, 0), 1000 * MAX_RETARGET_FACTOR);
+    assert_eq!(retarget_difficulty(1000, 1), 1000 * MAX_RETARGET_FACTOR);
+    assert_eq!(retarget_difficulty(1000, target_timespan * 100), 1000 / MAX_RETARGET_FACTOR);
+    // Never below the floor or past u64
+    assert_eq!(retarget_difficulty(MIN_DIFFICULTY, target_timespan * 2), MIN_DIFFICULTY);
+    assert_eq!(retarget_difficulty(u64::MAX, 0), u64::MAX);
+}
+
+#[tokio::test]
+async fn test_difficulty_retargets_after_window() {
+    println!("\n=== Running Difficulty Retarget Test ===");
+    let initial_time = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.retarget_window_start, initial_time);
+
+    // Fast-forward to one mine short of a full window
+    let mut account = context.banks_client.get_account(state_account).await.unwrap().unwrap();
+    let mut state = unpack_account::<MrFreshState>(&account.data).unwrap();
+    state.total_transactions = RETARGET_WINDOW - 1;
+    state.serialize(&mut &mut account.data[DISCRIMINATOR_LEN..]).unwrap();
+    context.set_account(&state_account, &account.into());
+
+    // The window closes in half the target time, so difficulty doubles
+    let window_end = initial_time + RETARGET_WINDOW as i64 * TARGET_MINE_INTERVAL / 2;
+    context.set_sysvar(&Clock {
+        slot: 1,
+        epoch_start_timestamp: initial_time,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: window_end,
+    });
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    process_mining_transaction(&mut context.banks_client, mine_instruction, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.total_transactions, RETARGET_WINDOW);
+    assert_eq!(state.mining_difficulty, 2000);
+    assert_eq!(state.retarget_window_start, window_end);
+}
+
+#[test]
+fn test_meets_difficulty() {
+    assert!(meets_difficulty(&[0; 32], u64::MAX));
+    assert!(meets_difficulty(&[0xff; 32], 1));
+    assert!(!meets_difficulty(&[0xff; 32], 2));
+
+    // The target halves as difficulty doubles
+    let mut hash = [0; 32];
+    hash[0] = 0x7f;
+    assert!(meets_difficulty(&hash, 2));
+    hash[0] = 0x80;
+    assert!(!meets_difficulty(&hash, 2));
+}
+
+#[tokio::test]
+async fn test_mine_with_proof() {
+    println!("\n=== Running Proof of Work Test ===");
+    let initial_time = 1000;
+    let mining_difficulty = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+    let other_miner = create_funded_miner(&mut context.banks_client, &payer, &context.last_blockhash)
+        .await
+        .unwrap();
+    let other_token_account = create_token_account(
+        &mut context.banks_client,
+        &payer,
+        &context.last_blockhash,
+        &mint.pubkey(),
+        &other_miner.pubkey(),
+        &spl_token::id(),
+    ).await.unwrap();
+
+    // Proofs are required from Initialize on, so plain Mine is refused
+    assert!(verify_mining_result(&mut context.banks_client, &state_account, None).await.unwrap().proof_required);
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    let result = process_mining_transaction(&mut context.banks_client, mine_instruction.clone(), &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::ProofRequired);
+
+    // Proofs are judged against the challenge in the state
+    let challenge = verify_mining_result(&mut context.banks_client, &state_account, None).await.unwrap().challenge;
+    let bad_nonce = find_nonce(&challenge, &miner.pubkey(), mining_difficulty, false);
+    let instruction = create_mine_with_proof_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id(), bad_nonce);
+    let result = process_mining_transaction(&mut context.banks_client, instruction, &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::InvalidProof);
+
+    // Committing is no way around the proof
+    let commit = create_commit_mine_instruction(&program_id, &state_account, &miner.pubkey(), [1; 32], bad_nonce);
+    let result = process_mining_transaction(&mut context.banks_client, commit, &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::InvalidProof);
+
+    // Any mine rotates the challenge, so a nonce found before it goes stale
+    let other_nonce = find_nonce(&challenge, &other_miner.pubkey(), mining_difficulty, true);
+    let instruction = create_mine_with_proof_instruction(&program_id, &state_account, &other_miner, &mint.pubkey(), &other_token_account, &spl_token::id(), other_nonce);
+    process_mining_transaction(&mut context.banks_client, instruction, &payer, &other_miner, context.last_blockhash)
+        .await
+        .unwrap();
+    let rotated = next_challenge(&challenge, 1);
+    let stale_nonce = (0..)
+        .find(|nonce| {
+            meets_difficulty(&proof_hash(&challenge, &miner.pubkey(), *nonce), mining_difficulty)
+                && !meets_difficulty(&proof_hash(&rotated, &miner.pubkey(), *nonce), mining_difficulty)
+        })
+        .unwrap();
+    let instruction = create_mine_with_proof_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id(), stale_nonce);
+    let result = process_mining_transaction(&mut context.banks_client, instruction, &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::InvalidProof);
+    let nonce = find_nonce(&rotated, &miner.pubkey(), mining_difficulty, true);
+    let instruction = create_mine_with_proof_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id(), nonce);
+    process_mining_transaction(&mut context.banks_client, instruction, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+
+    let expected_reward = calculate_expected_reward(initial_time, initial_time, mining_difficulty);
+    verify_mining_result(&mut context.banks_client, &state_account, Some(2 * expected_reward))
+        .await
+        .unwrap();
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None).await.unwrap();
+    assert_eq!(state.challenge, next_challenge(&rotated, 1), "Challenge must rotate after every mine");
+
+    // Accepting plain Mine again is a timelocked change like any other
+    let instruction = create_update_proof_requirement_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, false);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    let mine_time = initial_time + TIMELOCK_DELAY;
+    warp_with_slot_hashes(&mut context, 2, mine_time, &[]).await;
+    let execute = create_resolve_change_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, MrFreshInstruction::ExecuteQueuedChange);
+    let transaction = Transaction::new_signed_with_payer(&[execute], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    context.banks_client.process_transaction(transaction).await.unwrap();
+    assert!(!verify_mining_result(&mut context.banks_client, &state_account, None).await.unwrap().proof_required);
+    process_mining_transaction(&mut context.banks_client, mine_instruction, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+    let expected_supply = 2 * expected_reward + calculate_expected_reward(initial_time, mine_time, mining_difficulty);
+    verify_mining_result(&mut context.banks_client, &state_account, Some(expected_supply))
+        .await
+        .unwrap();
+}
+
+// Asserts a hit count sits within five standard deviations of the binomial mean
+fn assert_hit_rate(label: &str, hits: u64, samples: u64, odds: u64) {
+    let p = 1.0 / odds as f64;
+    let expected = samples as f64 * p;
+    let tolerance = 5.0 * (samples as f64 * p * (1.0 - p)).sqrt();
+    assert!(
+        (hits as f64 - expected).abs() <= tolerance,
+        "{} hit {} times in {} rolls, expected {:.1} ± {:.1}",
+        label,
+        hits,
+        samples,
+        expected,
+        tolerance
+    );
+}
+
+#[test]
+fn test_bonus_rolls_match_configured_odds() {
+    println!("\n=== Running Bonus Roll Odds Test ===");
+    let slot_hashes: Vec<[u8; 32]> = (0u64..1000).map(|i| solana_program::hash::hash(&i.to_le_bytes()).to_bytes()).collect();
+    let miners: Vec<Pubkey> = (0..100).map(|_| Pubkey::new_unique()).collect();
+    let samples = (slot_hashes.len() * miners.len()) as u64;
+
+    for (burst_odds, purr_odds) in [(ENERGY_BURST_INTERVAL, LUCKY_PURR_CHANCE), (2, 7), (10, 3)] {
+        let (mut bursts, mut purrs, mut both) = (0, 0, 0);
+        for (i, slot_hash) in slot_hashes.iter().enumerate() {
+            for miner in &miners {
+                let burst = bonus_roll(slot_hash, miner, i as u64, ENERGY_BURST_SALT) % burst_odds == 0;
+                let purr = bonus_roll(slot_hash, miner, i as u64, LUCKY_PURR_SALT) % purr_odds == 0;
+                bursts += burst as u64;
+                purrs += purr as u64;
+                both += (burst && purr) as u64;
+            }
+        }
+        assert_hit_rate("Energy burst", bursts, samples, burst_odds);
+        assert_hit_rate("Lucky purr", purrs, samples, purr_odds);
+        // Independent rolls land together at the product of their odds
+        assert_hit_rate("Both bonuses", both, samples, burst_odds * purr_odds);
+    }
+}
+
+#[test]
+fn test_single_transaction_reward_matches_average_reveal() {
+    let config = FreshConfig::default();
+    let base_reward = 1_000_000;
+    assert_eq!(single_transaction_reward(base_reward, &FreshConfig { energy_burst_bonus: 100, lucky_purr_bonus: 100, ..config.clone() }, 0, true), base_reward);
+
+    // Average what reveals pay over many seeds, rolling the way process_mining does
+    let miner = Pubkey::new_unique();
+    let (mut paid, mut paid_between_bursts, mut samples) = (0u64, 0u64, 0u64);
+    for seed in 0u64..100_000 {
+        let seed = solana_program::hash::hash(&seed.to_le_bytes()).to_bytes();
+        samples += 1;
+        if bonus_roll(&seed, &miner, 0, POOP_SALT) % POOP_CHANCE == 0 {
+            continue;
+        }
+        let mut reward = base_reward;
+        if bonus_roll(&seed, &miner, 0, LUCKY_PURR_SALT) % config.lucky_purr_chance == 0 {
+            reward = reward * config.lucky_purr_bonus / 100;
+        }
+        paid_between_bursts += reward;
+        if bonus_roll(&seed, &miner, 0, ENERGY_BURST_SALT) % config.energy_burst_interval == 0 {
+            reward = reward * config.energy_burst_bonus / 100;
+        }
+        paid += reward;
+    }
+    for (burst_ready, paid) in [(true, paid), (false, paid_between_bursts)] {
+        let average = paid / samples;
+        let expected = single_transaction_reward(base_reward, &config, POOP_CHANCE, burst_ready);
+        assert!(expected.abs_diff(average) < expected / 200, "expected {} against an average reveal of {}", expected, average);
+    }
+}
+
+#[test]
+fn test_bonus_roll_depends_on_every_input() {
+    let slot_hash = solana_program::hash::hash(b"slot").to_bytes();
+    let other_slot_hash = solana_program::hash::hash(b"other slot").to_bytes();
+    let miner = Pubkey::new_unique();
+    let roll = bonus_roll(&slot_hash, &miner, 7, LUCKY_PURR_SALT);
+
+    assert_eq!(roll, bonus_roll(&slot_hash, &miner, 7, LUCKY_PURR_SALT));
+    assert_ne!(roll, bonus_roll(&other_slot_hash, &miner, 7, LUCKY_PURR_SALT));
+    assert_ne!(roll, bonus_roll(&slot_hash, &Pubkey::new_unique(), 7, LUCKY_PURR_SALT));
+    assert_ne!(roll, bonus_roll(&slot_hash, &miner, 8, LUCKY_PURR_SALT));
+    assert_ne!(roll, bonus_roll(&slot_hash, &miner, 7, ENERGY_BURST_SALT));
+
+    // Across reveal seeds the same miner and commit slot still hit about 1 in 100 times
+    let hits = (0u64..100_000)
+        .filter(|i| bonus_roll(&solana_program::hash::hash(&i.to_le_bytes()).to_bytes(), &miner, 7, LUCKY_PURR_SALT) % LUCKY_PURR_CHANCE == 0)
+        .count() as u64;
+    assert_hit_rate("Lucky purr for one miner", hits, 100_000, LUCKY_PURR_CHANCE);
+}
+
+#[test]
+fn test_slot_hash_lookup() {
+    // The SlotHashes wire format: an entry count, then (slot, hash) newest first
+    let entries = [(105u64, [5u8; 32]), (104, [4; 32]), (101, [1; 32]), (100, [0; 32])];
+    let mut data = (entries.len() as u64).to_le_bytes().to_vec();
+    for (slot, hash) in &entries {
+        data.extend_from_slice(&slot.to_le_bytes());
+        data.extend_from_slice(hash);
+    }
+
+    assert_eq!(slot_hash_entries(&data).next(), Some((105, [5; 32])));
+    assert_eq!(slot_hash_at_or_after(&data, 104), Some([4; 32]));
+    assert_eq!(slot_hash_at_or_after(&data, 100), Some([0; 32]));
+    // Skipped slots fall through to the next slot that was produced
+    assert_eq!(slot_hash_at_or_after(&data, 102), Some([4; 32]));
+    assert_eq!(slot_hash_at_or_after(&data, 106), None);
+
+    // Trailing bytes past the entry count and truncated entries are ignored
+    let mut padded = data.clone();
+    padded.extend_from_slice(&[9; 40]);
+    assert_eq!(slot_hash_entries(&padded).count(), entries.len());
+    assert_eq!(slot_hash_entries(&data[..data.len() - 1]).count(), entries.len() - 1);
+    assert_eq!(slot_hash_entries(&[]).next(), None);
+}
+
+#[tokio::test]
+async fn test_commit_reveal_mining() {
+    println!("\n=== Running Commit-Reveal Mining Test ===");
+    let initial_time = 1000;
+    let mining_difficulty = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+    let commitment_address = find_commitment_address(&program_id, &miner.pubkey()).0;
+    let secret = [7u8; 32];
+    let reveal = create_reveal_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id(), secret);
+
+    // A commitment address funded by someone else must not lock the miner out
+    prefund_account(&mut context, &commitment_address).await;
+
+    let commit_slot = 2001;
+    warp_with_slot_hashes(&mut context, commit_slot, initial_time, &[(commit_slot - 1, Hash::new_unique())]).await;
+    let commit = create_commit_mine_instruction(&program_id, &state_account, &miner.pubkey(), commitment_hash(&secret, &miner.pubkey()), 0);
+    process_mining_transaction(&mut context.banks_client, commit, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+    let account = context.banks_client.get_account(commitment_address).await.unwrap().unwrap();
+    let commitment = unpack_account::<MineCommitment>(&account.data).unwrap();
+    assert_eq!(commitment.commit_slot, commit_slot);
+    assert_eq!(commitment.target_slot(), commit_slot + REVEAL_DELAY_SLOTS);
+
+    // One open commitment per miner
+    let commit = create_commit_mine_instruction(&program_id, &state_account, &miner.pubkey(), [1; 32], 0);
+    let result = process_mining_transaction(&mut context.banks_client, commit, &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::CommitmentPending);
+
+    // Even a first commit spends the turn, so a plain mine has to wait
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    let result = process_mining_transaction(&mut context.banks_client, mine_instruction.clone(), &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::CooldownActive);
+    let record = get_miner_record(&mut context.banks_client, &program_id, &miner.pubkey()).await;
+    assert_eq!(record.mine_count, 0);
+    assert_eq!(record.last_mining_timestamp, initial_time);
+
+    // The target slot hash doesn't exist yet
+    let result = process_mining_transaction(&mut context.banks_client, reveal.clone(), &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::RevealTooEarly);
+
+    let target_slot_hash = Hash::new_unique();
+    let reveal_slot = commitment.target_slot() + 2;
+    let slot_hashes = [
+        (reveal_slot - 1, Hash::new_unique()),
+        (commitment.target_slot(), target_slot_hash),
+        (commitment.target_slot() - 1, Hash::new_unique()),
+    ];
+    warp_with_slot_hashes(&mut context, reveal_slot, initial_time, &slot_hashes).await;
+    let wrong_reveal = create_reveal_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id(), [8; 32]);
+    let result = process_mining_transaction(&mut context.banks_client, wrong_reveal, &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::InvalidReveal);
+
+    process_mining_transaction(&mut context.banks_client, reveal.clone(), &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+
+    // The bonuses follow the secret and the target slot hash alone
+    let seed = reveal_seed(&secret, &target_slot_hash.to_bytes());
+    let mut expected_reward = calculate_base_reward(initial_time, initial_time, mining_difficulty);
+    if bonus_roll(&seed, &miner.pubkey(), commit_slot, ENERGY_BURST_SALT) % ENERGY_BURST_INTERVAL == 0 {
+        expected_reward = expected_reward * ENERGY_BURST_BONUS / 100;
+    }
+    if bonus_roll(&seed, &miner.pubkey(), commit_slot, LUCKY_PURR_SALT) % LUCKY_PURR_CHANCE == 0 {
+        expected_reward = expected_reward * LUCKY_PURR_BONUS / 100;
+    }
+    verify_mining_result(&mut context.banks_client, &state_account, Some(expected_reward))
+        .await
+        .unwrap();
+    assert!(context.banks_client.get_account(commitment_address).await.unwrap().is_none(), "Reveal should close the commitment");
+
+    // After a nap the miner commits again
+    let second_secret = [9u8; 32];
+    let second_commit_time = initial_time + MINING_COOLDOWN;
+    let second_commit_slot = 3001;
+    warp_with_slot_hashes(&mut context, second_commit_slot, second_commit_time, &[(second_commit_slot - 1, Hash::new_unique())]).await;
+    let commit = create_commit_mine_instruction(&program_id, &state_account, &miner.pubkey(), commitment_hash(&second_secret, &miner.pubkey()), 0);
+    process_mining_transaction(&mut context.banks_client, commit.clone(), &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+
+    // The commit spent this turn, so a plain mine has to wait
+    let result = process_mining_transaction(&mut context.banks_client, mine_instruction, &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::CooldownActive);
+
+    // Sitting on the secret past expiry forfeits the mine
+    let late_slot = second_commit_slot + COMMIT_EXPIRY_SLOTS + 1;
+    warp_with_slot_hashes(&mut context, late_slot, second_commit_time, &[(late_slot - 1, Hash::new_unique())]).await;
+    let late_reveal = create_reveal_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id(), second_secret);
+    let result = process_mining_transaction(&mut context.banks_client, late_reveal, &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::CommitmentExpired);
+    let result = process_mining_transaction(&mut context.banks_client, commit, &payer, &miner, context.last_blockhash).await;
+    assert_fresh_error(result, FreshError::CooldownActive);
+
+    // A full nap later the expired commitment is simply replaced
+    let third_commit_slot = late_slot + 10;
+    warp_with_slot_hashes(&mut context, third_commit_slot, second_commit_time + MINING_COOLDOWN, &[(third_commit_slot - 1, Hash::new_unique())]).await;
+    let commit = create_commit_mine_instruction(&program_id, &state_account, &miner.pubkey(), [3; 32], 0);
+    process_mining_transaction(&mut context.banks_client, commit, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+    let account = context.banks_client.get_account(commitment_address).await.unwrap().unwrap();
+    assert_eq!(unpack_account::<MineCommitment>(&account.data).unwrap().commit_slot, third_commit_slot);
+
+    let record = get_miner_record(&mut context.banks_client, &program_id, &miner.pubkey()).await;
+    assert_eq!(record.mine_count, 1);
+    assert_eq!(record.total_rewards, expected_reward);
+}
+
+#[tokio::test]
+async fn test_poop_model() {
+    println!("\n=== Running Poop Model Test ===");
+    let initial_time = 100_000;
+    let mining_difficulty = 1000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) = setup_plain_mining_fixture(&mut context, &program_id, &spl_token::id()).await;
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.poop_chance, 0, "The fixture initializes with poop off");
+
+    // Every mine failing would halt emission
+    let instruction = create_update_poop_model_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), 0, 1, false);
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+    let result = context.banks_client.process_transaction(transaction).await;
+    assert_fresh_error(result, FreshError::InvalidConfig);
+
+    let base_reward = calculate_base_reward(initial_time, initial_time, mining_difficulty);
+    let mine_reward = single_transaction_reward(base_reward, &FreshConfig::default(), POOP_CHANCE, true);
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    let reveal = create_reveal_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id(), [7; 32]);
+    let commit = create_commit_mine_instruction(&program_id, &state_account, &miner.pubkey(), commitment_hash(&[7; 32], &miner.pubkey()), 0);
+    let (mut now, mut slot, mut mines, mut total_supply) = (initial_time, 1, 0, 0);
+
+    for (change_id, consolation) in [(0, true), (1, false)] {
+        let instruction = create_update_poop_model_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), change_id, POOP_CHANCE, consolation);
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+        context.banks_client.process_transaction(transaction).await.unwrap();
+        now += TIMELOCK_DELAY;
+        slot += 10;
+        warp_with_slot_hashes(&mut context, slot, now, &[]).await;
+        let execute = create_resolve_change_instruction(&program_id, &payer.pubkey(), &payer.pubkey(), change_id, MrFreshInstruction::ExecuteQueuedChange);
+        let transaction = Transaction::new_signed_with_payer(&[execute], Some(&payer.pubkey()), &[&payer], context.last_blockhash);
+        context.banks_client.process_transaction(transaction).await.unwrap();
+        let state = verify_mining_result(&mut context.banks_client, &state_account, None).await.unwrap();
+        assert_eq!(state.poop_chance, POOP_CHANCE);
+        assert_eq!(state.poop_consolation, consolation);
+
+        // Mine could dodge a poop by checking the roll first, so it never
+        // rolls one, even on a slot hash a reveal would poop on, and pays
+        // the expected value instead
+        for _ in 0..2 {
+            let poop_slot_hash = find_slot_hash(|hash| bonus_roll(hash, &miner.pubkey(), mines, POOP_SALT) % POOP_CHANCE == 0);
+            now += MINING_COOLDOWN;
+            slot += 10;
+            warp_with_slot_hashes(&mut context, slot, now, &[(slot - 1, poop_slot_hash)]).await;
+            process_mining_transaction(&mut context.banks_client, mine_instruction.clone(), &payer, &miner, context.last_blockhash)
+                .await
+                .unwrap();
+            mines += 1;
+            total_supply += mine_reward;
+            verify_mining_result(&mut context.banks_client, &state_account, Some(total_supply))
+                .await
+                .unwrap();
+        }
+
+        // A reveal rolls for real: a poop spends the turn and pays nothing
+        now += MINING_COOLDOWN;
+        slot += 10;
+        let commit_slot = slot;
+        warp_with_slot_hashes(&mut context, commit_slot, now, &[]).await;
+        process_mining_transaction(&mut context.banks_client, commit.clone(), &payer, &miner, context.last_blockhash)
+            .await
+            .unwrap();
+        let poop_slot_hash = find_slot_hash(|hash| {
+            bonus_roll(&reveal_seed(&[7; 32], hash), &miner.pubkey(), commit_slot, POOP_SALT) % POOP_CHANCE == 0
+        });
+        slot = commit_slot + REVEAL_DELAY_SLOTS + 1;
+        warp_with_slot_hashes(&mut context, slot, now, &[(commit_slot + REVEAL_DELAY_SLOTS, poop_slot_hash)]).await;
+        process_mining_transaction(&mut context.banks_client, reveal.clone(), &payer, &miner, context.last_blockhash)
+            .await
+            .unwrap();
+        mines += 1;
+        let state = verify_mining_result(&mut context.banks_client, &state_account, Some(total_supply))
+            .await
+            .unwrap();
+        assert_eq!(state.total_transactions, mines);
+        assert_eq!(get_token_balance(&mut context.banks_client, &miner_token_account).await, total_supply);
+
+        // The consolation halves the nap, without it the poop costs a full one
+        let record = get_miner_record(&mut context.banks_client, &program_id, &miner.pubkey()).await;
+        assert_eq!(record.mine_count, mines);
+        let nap_start = if consolation { now - MINING_COOLDOWN / 2 } else { now };
+        assert_eq!(record.last_mining_timestamp, nap_start);
+
+        now += MINING_COOLDOWN / 2;
+        slot += 1;
+        warp_with_slot_hashes(&mut context, slot, now, &[]).await;
+        let result = process_mining_transaction(&mut context.banks_client, mine_instruction.clone(), &payer, &miner, context.last_blockhash).await;
+        if consolation {
+            result.unwrap();
+            mines += 1;
+            total_supply += mine_reward;
+        } else {
+            assert_fresh_error(result, FreshError::CooldownActive);
+        }
+    }
+
+    // A newcomer whose first reveal poops mints nothing but still counts as a miner
+    let newcomer = create_funded_miner(&mut context.banks_client, &payer, &context.last_blockhash)
+        .await
+        .unwrap();
+    let newcomer_token_account = create_token_account(
+        &mut context.banks_client,
+        &payer,
+        &context.last_blockhash,
+        &mint.pubkey(),
+        &newcomer.pubkey(),
+        &spl_token::id(),
+    ).await.unwrap();
+    slot += 10;
+    let commit_slot = slot;
+    warp_with_slot_hashes(&mut context, commit_slot, now, &[]).await;
+    let commit = create_commit_mine_instruction(&program_id, &state_account, &newcomer.pubkey(), commitment_hash(&[7; 32], &newcomer.pubkey()), 0);
+    process_mining_transaction(&mut context.banks_client, commit, &payer, &newcomer, context.last_blockhash)
+        .await
+        .unwrap();
+    let poop_slot_hash = find_slot_hash(|hash| {
+        bonus_roll(&reveal_seed(&[7; 32], hash), &newcomer.pubkey(), commit_slot, POOP_SALT) % POOP_CHANCE == 0
+    });
+    warp_with_slot_hashes(&mut context, commit_slot + REVEAL_DELAY_SLOTS + 1, now, &[(commit_slot + REVEAL_DELAY_SLOTS, poop_slot_hash)]).await;
+    let reveal = create_reveal_mine_instruction(&program_id, &state_account, &newcomer, &mint.pubkey(), &newcomer_token_account, &spl_token::id(), [7; 32]);
+    process_mining_transaction(&mut context.banks_client, reveal, &payer, &newcomer, context.last_blockhash)
+        .await
+        .unwrap();
+    let state = verify_mining_result(&mut context.banks_client, &state_account, Some(total_supply))
+        .await
+        .unwrap();
+    assert_eq!(state.total_miners, 2);
+    assert_eq!(get_token_balance(&mut context.banks_client, &newcomer_token_account).await, 0);
+}
+
+#[test]
+fn test_poop_rolls_match_configured_odds() {
+    let slot_hashes: Vec<[u8; 32]> = (0u64..1000).map(|i| solana_program::hash::hash(&i.to_le_bytes()).to_bytes()).collect();
+    let miner = Pubkey::new_unique();
+    for poop_chance in [MIN_POOP_CHANCE, POOP_CHANCE, 100] {
+        let poops = slot_hashes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, slot_hash)| (0..100).map(move |mine_number| bonus_roll(slot_hash, &miner, i as u64 * 100 + mine_number, POOP_SALT)))
+            .filter(|roll| roll % poop_chance == 0)
+            .count() as u64;
+        assert_hit_rate("Poop", poops, 100_000, poop_chance);
+    }
+}
+
+// Deterministic pseudo-random inputs for the emission property tests
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+#[test]
+fn test_emission_at_matches_halving_loop() {
+    let mut rng = 0x5eed_f00d_u64;
+    for _ in 0..20_000 {
+        let schedule = EmissionSchedule {
+            mode: EmissionMode::Step,
+            initialization_timestamp: (xorshift(&mut rng) % 2_000_000_000) as i64,
+            halving_interval: 1 + (xorshift(&mut rng) % (2 * HALVING_INTERVAL as u64)) as i64,
+            initial_base_reward: xorshift(&mut rng) >> (xorshift(&mut rng) % 64),
+            max_supply: MAX_SUPPLY,
+            total_supply: xorshift(&mut rng) % (MAX_SUPPLY + 1),
+        };
+        let difficulty = MIN_DIFFICULTY + xorshift(&mut rng) % 1_000_000;
+        // From one interval before the start to 80 halvings in, past the last reward
+        let elapsed = (xorshift(&mut rng) % (81 * schedule.halving_interval as u64)) as i64 - schedule.halving_interval;
+        let timestamp = schedule.initialization_timestamp + elapsed;
+
+        let emission = schedule.emission_at(timestamp, difficulty);
+        let context = format!("{:?} at {} with difficulty {}", schedule, timestamp, difficulty);
+        assert_eq!(emission.reward, halving_loop_reward(schedule.initial_base_reward, schedule.halving_interval, elapsed, difficulty), "{}", context);
+        assert_eq!(emission.base_reward, halving_loop_reward(schedule.initial_base_reward, schedule.halving_interval, elapsed, 1), "{}", context);
+        assert_eq!(emission.remaining_supply, MAX_SUPPLY - schedule.total_supply);
+
+        // The next halving is the first moment the epoch ticks over
+        assert!(emission.next_halving_at > timestamp, "{}", context);
+        assert_eq!(schedule.emission_at(emission.next_halving_at - 1, difficulty).epoch, emission.epoch, "{}", context);
+        let after = schedule.emission_at(emission.next_halving_at, difficulty);
+        assert_eq!(after.epoch, emission.epoch + 1, "{}", context);
+        assert_eq!(after.base_reward, emission.base_reward / 2, "{}", context);
+    }
+}
+
+#[test]
+fn test_emission_at_extremes() {
+    let schedule = EmissionSchedule {
+        mode: EmissionMode::Step,
+        initialization_timestamp: 1000,
+        halving_interval: HALVING_INTERVAL,
+        initial_base_reward: INITIAL_BASE_REWARD,
+        max_supply: MAX_SUPPLY,
+        total_supply: 0,
+    };
+    let start = schedule.emission_at(1000, 1000);
+    assert_eq!(start, EmissionInfo {
+        epoch: 0,
+        base_reward: INITIAL_BASE_REWARD,
+        reward: INITIAL_BASE_REWARD / 1000,
+        next_halving_at: 1000 + HALVING_INTERVAL,
+        remaining_supply: MAX_SUPPLY,
+    });
+    // A clock behind the start time is still the first epoch
+    assert_eq!(schedule.emission_at(i64::MIN, 1000), start);
+
+    // The published schedule is this one counted from initialization
+    for elapsed in [0, HALVING_INTERVAL - 1, HALVING_INTERVAL, 5 * HALVING_INTERVAL + 7] {
+        let info = schedule.emission_at(1000 + elapsed, 1000);
+        assert_eq!(emission_at(elapsed, 1000), EmissionInfo { next_halving_at: info.next_halving_at - 1000, ..info });
+    }
+
+    // Far past the last halving nothing overflows and nothing is paid
+    let end = schedule.emission_at(i64::MAX, 1000);
+    assert_eq!(end.base_reward, 0);
+    assert_eq!(end.reward, 0);
+    assert_eq!(end.next_halving_at, i64::MAX);
+
+    let mut state = <MrFreshStateView as Zeroable>::zeroed();
+    state.initialization_timestamp = 1000.into();
+    state.mining_difficulty = 1000.into();
+    let config = FreshConfig::default();
+    assert_eq!(EmissionSchedule::new(state.emission_mode(), 1000, 0, &config), schedule);
+    assert_eq!(expected_mining_reward(&state, &config, 1000).unwrap(), INITIAL_BASE_REWARD / 1000);
+    assert!(matches!(expected_mining_reward(&state, &config, i64::MAX), Err(FreshError::MaxSupplyReached)));
+    state.total_supply = MAX_SUPPLY.into();
+    assert!(matches!(expected_mining_reward(&state, &config, 1000), Err(FreshError::MaxSupplyReached)));
+    state.emission_mode = 1;
+    assert_eq!(state.emission_mode(), EmissionMode::ExponentialDecay);
+}
+
+#[test]
+fn test_exponential_decay_tracks_half_life() {
+    let mut rng = 0xdeca_u64;
+    for _ in 0..20_000 {
+        let step = EmissionSchedule {
+            mode: EmissionMode::Step,
+            initialization_timestamp: (xorshift(&mut rng) % 2_000_000_000) as i64,
+            halving_interval: 1 + (xorshift(&mut rng) % (2 * HALVING_INTERVAL as u64)) as i64,
+            initial_base_reward: xorshift(&mut rng) >> (xorshift(&mut rng) % 64),
+            max_supply: MAX_SUPPLY,
+            total_supply: xorshift(&mut rng) % (MAX_SUPPLY + 1),
+        };
+        let decay = EmissionSchedule { mode: EmissionMode::ExponentialDecay, ..step };
+        let elapsed = (xorshift(&mut rng) % (70 * step.halving_interval as u64)) as i64;
+        let timestamp = step.initialization_timestamp + elapsed;
+        let context = format!("{:?} at {}", decay, timestamp);
+
+        // Epochs, halvings and everything but the reward match the step curve
+        let stepped = step.emission_at(timestamp, 1);
+        let decayed = decay.emission_at(timestamp, 1);
+        assert_eq!(EmissionInfo { base_reward: stepped.base_reward, reward: stepped.reward, ..decayed }, stepped, "{}", context);
+
+        // Within an epoch the reward falls from the step value towards half of it
+        assert!(decayed.base_reward <= stepped.base_reward, "{}", context);
+        assert!(decayed.base_reward >= stepped.base_reward / 2, "{}", context);
+        let epoch_start = timestamp - elapsed % step.halving_interval;
+        assert_eq!(decay.emission_at(epoch_start, 1), step.emission_at(epoch_start, 1), "{}", context);
+
+        let exact = step.initial_base_reward as f64 * (-(elapsed as f64) / step.halving_interval as f64).exp2();
+        assert!((decayed.base_reward as f64 - exact).abs() <= exact * 1e-6 + 1.0, "{} gave {} for {}", context, decayed.base_reward, exact);
+    }
+}
+
+#[test]
+fn test_exponential_decay_is_monotonic() {
+    let schedule = EmissionSchedule {
+        mode: EmissionMode::ExponentialDecay,
+        initialization_timestamp: 0,
+        halving_interval: HALVING_INTERVAL,
+        initial_base_reward: u64::MAX,
+        max_supply: MAX_SUPPLY,
+        total_supply: 0,
+    };
+    let mut rng = 0x0d0_u64;
+    let mut timestamp = -HALVING_INTERVAL;
+    let mut previous = schedule.emission_at(timestamp, 1).base_reward;
+    assert_eq!(previous, u64::MAX);
+    while previous > 0 {
+        timestamp += 1 + (xorshift(&mut rng) % 100_000) as i64;
+        let base_reward = schedule.emission_at(timestamp, 1).base_reward;
+        assert!(base_reward <= previous, "reward rose to {} at {}", base_reward, timestamp);
+        previous = base_reward;
+    }
+    assert_eq!(schedule.emission_at(i64::MAX, 1).base_reward, 0);
+}
+
+#[test]
+fn test_total_emission_converges_under_max_supply() {
+    // One miner mining every cooldown at the lowest difficulty, until the
+    // reward wears to zero
+    let lifetime_emission = |mode| {
+        let schedule = EmissionSchedule {
+            mode,
+            initialization_timestamp: 0,
+            halving_interval: HALVING_INTERVAL,
+            initial_base_reward: INITIAL_BASE_REWARD,
+            max_supply: MAX_SUPPLY,
+            total_supply: 0,
+        };
+        let mut total = 0u64;
+        for timestamp in (0..).step_by(MINING_COOLDOWN as usize) {
+            let emission = schedule.emission_at(timestamp, MIN_DIFFICULTY);
+            if emission.base_reward == 0 {
+                return total;
+            }
+            total += emission.reward;
+        }
+        unreachable!()
+    };
+    let step_total = lifetime_emission(EmissionMode::Step);
+    let decay_total = lifetime_emission(EmissionMode::ExponentialDecay);
+
+    // Both are geometric series: 2 half-lives of the first reward for the
+    // step curve and 1/ln 2 for the decay curve
+    let mines_per_half_life = (HALVING_INTERVAL / MINING_COOLDOWN) as f64;
+    let first_reward = (INITIAL_BASE_REWARD / MIN_DIFFICULTY) as f64;
+    let step_limit = 2.0 * first_reward * mines_per_half_life;
+    let decay_limit = first_reward * mines_per_half_life / std::f64::consts::LN_2;
+    assert!((step_total as f64 - step_limit).abs() < step_limit * 0.01, "{} vs {}", step_total, step_limit);
+    assert!((decay_total as f64 - decay_limit).abs() < decay_limit * 0.01, "{} vs {}", decay_total, decay_limit);
+    assert!(decay_total < step_total);
+
+    // Even a million miners never mining less often stay under the cap
+    assert!(step_total.checked_mul(1_000_000).unwrap() < MAX_SUPPLY);
+}
+
+#[tokio::test]
+async fn test_exponential_decay_mining() {
+    println!("\n=== Running Exponential Decay Mining Test ===");
+    let initial_time = 100_000;
+    let (mut context, program_id) = setup_test_context(initial_time, 1).await;
+    let payer = context.payer.insecure_clone();
+    let (miner, mint, miner_token_account, state_account) =
+        setup_mining_fixture_with(&mut context, &program_id, &spl_token::id(), EmissionMode::ExponentialDecay, false).await;
+    let state = verify_mining_result(&mut context.banks_client, &state_account, None)
+        .await
+        .unwrap();
+    assert_eq!(state.emission_mode, EmissionMode::ExponentialDecay);
+
+    // Half a half-life in, the reward is down by a factor of sqrt(2)
+    let mine_time = initial_time + HALVING_INTERVAL / 2;
+    warp_with_slot_hashes(&mut context, 2, mine_time, &[]).await;
+    let mine_instruction = create_mine_instruction(&program_id, &state_account, &miner, &mint.pubkey(), &miner_token_account, &spl_token::id());
+    process_mining_transaction(&mut context.banks_client, mine_instruction, &payer, &miner, context.last_blockhash)
+        .await
+        .unwrap();
+
+    let base_reward = (INITIAL_BASE_REWARD as f64 / std::f64::consts::SQRT_2 / 1000.0) as u64;
+    assert!(base_reward < calculate_base_reward(initial_time, mine_time, 1000));
+    let expected_reward = single_transaction_reward(base_reward, &FreshConfig::default(), 0, true);
+    verify_mining_result(&mut context.banks_client, &state_account, Some(expected_reward))
+        .await
+        .unwrap();
+    assert_eq!(get_token_balance(&mut context.banks_client, &miner_token_account).await, expected_reward);
+}